@@ -1,19 +1,21 @@
-use core::num::NonZeroUsize;
+use core::{cell::Cell, num::NonZeroUsize};
 
 use crate::Z_MAX_MTU;
-use heapless::Vec;
+use heapless::{Deque, Vec};
 use thiserror::Error;
 
-#[derive(Error, Debug, Clone, Copy)]
-pub struct DidntWrite;
-
-impl core::fmt::Display for DidntWrite {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Didn't write")
-    }
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WriteError {
+    #[error("Didn't write")]
+    DidntWrite,
+    /// The buffer would have to hold `needed` bytes but only has room for `capacity`.
+    #[error("Capacity exceeded")]
+    CapacityExceeded { needed: usize, capacity: usize },
 }
 
 #[derive(Error, Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct DidntRead;
 
 impl core::fmt::Display for DidntRead {
@@ -23,19 +25,68 @@ impl core::fmt::Display for DidntRead {
 }
 
 pub trait Writer {
-    fn write(&mut self, bytes: &[u8]) -> Result<(), DidntWrite>;
-    fn write_exact(&mut self, bytes: &[u8]) -> Result<(), DidntWrite>;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), WriteError>;
+    fn write_exact(&mut self, bytes: &[u8]) -> Result<(), WriteError>;
 
-    fn write_u8(&mut self, byte: u8) -> Result<(), DidntWrite> {
+    /// Total number of bytes written so far.
+    fn written(&self) -> usize;
+
+    fn write_u8(&mut self, byte: u8) -> Result<(), WriteError> {
         self.write_exact(core::slice::from_ref(&byte))
     }
+
+    fn write_u16_le(&mut self, value: u16) -> Result<(), WriteError> {
+        self.write_exact(&value.to_le_bytes())
+    }
+
+    fn write_u32_le(&mut self, value: u32) -> Result<(), WriteError> {
+        self.write_exact(&value.to_le_bytes())
+    }
+}
+
+/// Hands every write straight to a closure instead of buffering it, so a
+/// message can be streamed in the chunks its encoder produces.
+pub(crate) struct ChunkWriter<F> {
+    on_chunk: F,
+    written: usize,
 }
 
+impl<F: FnMut(&[u8])> ChunkWriter<F> {
+    pub(crate) fn new(on_chunk: F) -> Self {
+        ChunkWriter {
+            on_chunk,
+            written: 0,
+        }
+    }
+}
+
+impl<F: FnMut(&[u8])> Writer for ChunkWriter<F> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        self.write_exact(bytes)
+    }
+
+    fn write_exact(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        (self.on_chunk)(bytes);
+        self.written += bytes.len();
+        Ok(())
+    }
+
+    fn written(&self) -> usize {
+        self.written
+    }
+}
+
+/// Readers only take `&self` so that several slices borrowed through
+/// [`Reader::read_slice_in_place`] can be alive at the same time while decoding
+/// continues, implementors keep their cursor in a [`Cell`].
 pub trait Reader {
-    fn read(&mut self, into: &mut [u8]) -> Result<NonZeroUsize, DidntRead>;
-    fn read_exact(&mut self, into: &mut [u8]) -> Result<(), DidntRead>;
+    /// Reads at least one byte into `into`. A reader with nothing left must
+    /// fail rather than report an empty read, so decode loops always progress.
+    fn read(&self, into: &mut [u8]) -> Result<NonZeroUsize, DidntRead>;
+    fn read_exact(&self, into: &mut [u8]) -> Result<(), DidntRead>;
+    fn remaining(&self) -> usize;
 
-    fn read_u8(&mut self) -> Result<u8, DidntRead> {
+    fn read_u8(&self) -> Result<u8, DidntRead> {
         let mut byte = 0;
         let read = self.read(core::slice::from_mut(&mut byte))?;
         if read.get() == 1 {
@@ -45,8 +96,40 @@ pub trait Reader {
         }
     }
 
-    fn read_slice_in_place(&mut self, _len: usize) -> Result<&[u8], DidntRead> {
-        unimplemented!("read_slice_in_place")
+    fn read_u16_le(&self) -> Result<u16, DidntRead> {
+        let mut bytes = [0u8; 2];
+        self.read_exact(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn read_u32_le(&self) -> Result<u32, DidntRead> {
+        let mut bytes = [0u8; 4];
+        self.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    /// Borrows the next `len` bytes from the underlying buffer. Readers that
+    /// cannot lend their bytes keep this default, which fails to read.
+    fn read_slice_in_place(&self, _len: usize) -> Result<&[u8], DidntRead> {
+        Err(DidntRead)
+    }
+
+    /// Advances past the next `n` bytes, e.g. an unknown extension. Nothing
+    /// is consumed if fewer than `n` bytes are left.
+    fn skip(&self, n: usize) -> Result<(), DidntRead> {
+        if n > self.remaining() {
+            return Err(DidntRead);
+        }
+
+        let mut scratch = [0u8; 16];
+        let mut left = n;
+        while left > 0 {
+            let chunk = left.min(scratch.len());
+            self.read_exact(&mut scratch[..chunk])?;
+            left -= chunk;
+        }
+
+        Ok(())
     }
 }
 
@@ -75,27 +158,61 @@ impl ZVec {
         self.vec.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// Iterates over the bytes written so far.
+    pub fn iter(&self) -> core::slice::Iter<'_, u8> {
+        self.vec.iter()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
+    }
+
+    /// Bytes that can still be written before the buffer is full.
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.vec.truncate(len)
+    }
+
     pub unsafe fn set_len(&mut self, len: usize) {
         self.vec.set_len(len)
     }
 
     pub fn extract_slice<'a>(&'a mut self, len: usize) -> Result<ZVecSlice<'a>, DidntRead> {
-        if len > self.vec.capacity() {
+        if len > self.capacity() {
             return Err(DidntRead);
         }
         Ok(ZVecSlice::new(self, len))
     }
 }
 
+impl<'a> IntoIterator for &'a ZVec {
+    type Item = &'a u8;
+    type IntoIter = core::slice::Iter<'a, u8>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 pub struct ZVecSlice<'a> {
     vec: &'a mut ZVec,
-    len: usize,
-    idx: usize,
+    /// Length of the vector to restore once the slice is dropped.
+    restore_len: usize,
+    /// Length the slice was extracted with, the most it can be truncated to.
+    extracted: usize,
+    idx: Cell<usize>,
 }
 
 impl<'a> ZVecSlice<'a> {
     fn new(v: &'a mut ZVec, len: usize) -> Self {
-        let o_len = v.len();
+        let restore_len = v.len();
 
         unsafe {
             v.set_len(len);
@@ -103,26 +220,32 @@ impl<'a> ZVecSlice<'a> {
 
         ZVecSlice {
             vec: v,
-            len: o_len,
-            idx: 0,
+            restore_len,
+            extracted: len,
+            idx: Cell::new(0),
         }
     }
 
-    pub fn truncate(&mut self, len: usize) {
-        if len > self.vec.len() {
-            return;
+    /// Shrinks the active slice to its first `len` bytes, typically the
+    /// amount actually received into it. `len` may not exceed the length the
+    /// slice was extracted with nor drop bytes already read.
+    pub fn truncate(&mut self, len: usize) -> Result<(), DidntRead> {
+        if len > self.extracted || len < self.idx.get() {
+            return Err(DidntRead);
         }
 
         unsafe {
             self.vec.set_len(len);
         }
+
+        Ok(())
     }
 }
 
 impl Drop for ZVecSlice<'_> {
     fn drop(&mut self) {
         unsafe {
-            self.vec.set_len(self.len);
+            self.vec.set_len(self.restore_len);
         }
     }
 }
@@ -140,43 +263,58 @@ impl AsRef<[u8]> for ZVecSlice<'_> {
 }
 
 impl Writer for ZVec {
-    fn write(&mut self, bytes: &[u8]) -> Result<(), DidntWrite> {
-        self.vec.extend_from_slice(bytes).map_err(|_| DidntWrite)
+    fn write(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        self.write_exact(bytes)
     }
 
-    fn write_exact(&mut self, bytes: &[u8]) -> Result<(), DidntWrite> {
-        self.vec.extend_from_slice(bytes).map_err(|_| DidntWrite)
+    fn write_exact(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        if bytes.len() > self.remaining() {
+            return Err(WriteError::CapacityExceeded {
+                needed: self.len() + bytes.len(),
+                capacity: self.capacity(),
+            });
+        }
+
+        self.vec
+            .extend_from_slice(bytes)
+            .map_err(|_| WriteError::DidntWrite)
+    }
+
+    fn written(&self) -> usize {
+        self.vec.len()
     }
 }
 
 impl<'a> Reader for ZVecSlice<'a> {
-    fn read(&mut self, into: &mut [u8]) -> Result<NonZeroUsize, DidntRead> {
+    fn read(&self, into: &mut [u8]) -> Result<NonZeroUsize, DidntRead> {
         let len = into.len();
-        let remaining = self.vec.len() - self.idx;
+        let idx = self.idx.get();
+        let remaining = self.remaining();
         if remaining == 0 {
             return Err(DidntRead);
         }
         let to_read = core::cmp::min(len, remaining);
-        into[..to_read].copy_from_slice(&self.vec.as_slice()[self.idx..self.idx + to_read]);
-        self.idx += to_read;
+        into[..to_read].copy_from_slice(&self.vec.as_slice()[idx..idx + to_read]);
+        self.idx.set(idx + to_read);
         Ok(NonZeroUsize::new(to_read).unwrap())
     }
 
-    fn read_exact(&mut self, into: &mut [u8]) -> Result<(), DidntRead> {
+    fn read_exact(&self, into: &mut [u8]) -> Result<(), DidntRead> {
         let len = into.len();
-        let remaining = self.vec.len() - self.idx;
-        if remaining == 0 {
-            return Err(DidntRead);
-        }
-        if len > remaining {
+        let idx = self.idx.get();
+        if len > self.remaining() {
             return Err(DidntRead);
         }
-        into.copy_from_slice(&self.vec.as_slice()[self.idx..self.idx + len]);
-        self.idx += len;
+        into.copy_from_slice(&self.vec.as_slice()[idx..idx + len]);
+        self.idx.set(idx + len);
         Ok(())
     }
 
-    fn read_u8(&mut self) -> Result<u8, DidntRead> {
+    fn remaining(&self) -> usize {
+        self.vec.len() - self.idx.get()
+    }
+
+    fn read_u8(&self) -> Result<u8, DidntRead> {
         let mut byte = 0;
         let read = self.read(core::slice::from_mut(&mut byte))?;
         if read.get() == 1 {
@@ -186,16 +324,426 @@ impl<'a> Reader for ZVecSlice<'a> {
         }
     }
 
-    fn read_slice_in_place(&mut self, len: usize) -> Result<&[u8], DidntRead> {
-        let remaining = self.vec.len() - self.idx;
-        if remaining == 0 {
+    fn read_slice_in_place(&self, len: usize) -> Result<&[u8], DidntRead> {
+        let idx = self.idx.get();
+        if len > self.remaining() {
             return Err(DidntRead);
         }
-        if len > remaining {
+        let slice = &self.vec.as_slice()[idx..idx + len];
+        self.idx.set(idx + len);
+        Ok(slice)
+    }
+
+    fn skip(&self, n: usize) -> Result<(), DidntRead> {
+        if n > self.remaining() {
             return Err(DidntRead);
         }
-        let slice = &self.vec.as_slice()[self.idx..self.idx + len];
-        self.idx += len;
+        self.idx.set(self.idx.get() + n);
+        Ok(())
+    }
+}
+
+/// Writer over a caller provided buffer, e.g. a DMA region, not bound by
+/// `Z_MAX_MTU`.
+pub struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    idx: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        SliceWriter { buf, idx: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.idx
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.idx == 0
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.idx]
+    }
+}
+
+impl Writer for SliceWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        self.write_exact(bytes)
+    }
+
+    fn write_exact(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        let needed = self.idx + bytes.len();
+        if needed > self.buf.len() {
+            return Err(WriteError::CapacityExceeded {
+                needed,
+                capacity: self.buf.len(),
+            });
+        }
+        self.buf[self.idx..needed].copy_from_slice(bytes);
+        self.idx = needed;
+        Ok(())
+    }
+
+    fn written(&self) -> usize {
+        self.idx
+    }
+}
+
+/// Reader over a caller provided buffer.
+pub struct SliceReader<'a> {
+    buf: &'a [u8],
+    idx: Cell<usize>,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        SliceReader {
+            buf,
+            idx: Cell::new(0),
+        }
+    }
+}
+
+impl Reader for SliceReader<'_> {
+    fn read(&self, into: &mut [u8]) -> Result<NonZeroUsize, DidntRead> {
+        let idx = self.idx.get();
+        let to_read = core::cmp::min(into.len(), self.remaining());
+        let read = NonZeroUsize::new(to_read).ok_or(DidntRead)?;
+        into[..to_read].copy_from_slice(&self.buf[idx..idx + to_read]);
+        self.idx.set(idx + to_read);
+        Ok(read)
+    }
+
+    fn read_exact(&self, into: &mut [u8]) -> Result<(), DidntRead> {
+        let slice = self.read_slice_in_place(into.len())?;
+        into.copy_from_slice(slice);
+        Ok(())
+    }
+
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.idx.get()
+    }
+
+    fn read_slice_in_place(&self, len: usize) -> Result<&[u8], DidntRead> {
+        let idx = self.idx.get();
+        if len > self.remaining() {
+            return Err(DidntRead);
+        }
+        self.idx.set(idx + len);
+        Ok(&self.buf[idx..idx + len])
+    }
+
+    fn skip(&self, n: usize) -> Result<(), DidntRead> {
+        if n > self.remaining() {
+            return Err(DidntRead);
+        }
+        self.idx.set(self.idx.get() + n);
+        Ok(())
+    }
+}
+
+/// Byte ring for stream links, where a message may straddle several reads.
+///
+/// Received bytes are appended through [`RingBuf::writer`]. A decode then
+/// runs on [`RingBuf::reader`], which leaves the ring untouched so a decode
+/// failing on a partial message can be retried once more bytes arrived.
+/// After a successful one, [`RingBuf::consume`] drops the bytes it read.
+pub struct RingBuf<const N: usize> {
+    deque: Deque<u8, N>,
+}
+
+impl<const N: usize> RingBuf<N> {
+    pub fn new() -> Self {
+        RingBuf {
+            deque: Deque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deque.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deque.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn writer(&mut self) -> RingWriter<'_, N> {
+        RingWriter {
+            deque: &mut self.deque,
+            written: 0,
+        }
+    }
+
+    pub fn reader(&self) -> RingReader<'_, N> {
+        RingReader {
+            deque: &self.deque,
+            idx: Cell::new(0),
+        }
+    }
+
+    /// Drops the `n` oldest bytes, e.g. [`RingReader::position`] once a
+    /// message decoded.
+    pub fn consume(&mut self, n: usize) {
+        for _ in 0..n.min(self.deque.len()) {
+            self.deque.pop_front();
+        }
+    }
+}
+
+impl<const N: usize> Default for RingBuf<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Appends to a [`RingBuf`], a write either fits entirely or is refused.
+pub struct RingWriter<'a, const N: usize> {
+    deque: &'a mut Deque<u8, N>,
+    written: usize,
+}
+
+impl<const N: usize> Writer for RingWriter<'_, N> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        self.write_exact(bytes)
+    }
+
+    fn write_exact(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        let needed = self.deque.len() + bytes.len();
+        if needed > N {
+            return Err(WriteError::CapacityExceeded {
+                needed,
+                capacity: N,
+            });
+        }
+        for &b in bytes {
+            // Cannot fail, the room was checked above
+            let _ = self.deque.push_back(b);
+        }
+        self.written += bytes.len();
+        Ok(())
+    }
+
+    fn written(&self) -> usize {
+        self.written
+    }
+}
+
+/// Reads a [`RingBuf`] from its oldest byte without consuming it.
+///
+/// Only bytes on one side of the wrap can be lent by
+/// [`Reader::read_slice_in_place`], a slice straddling it fails to read.
+pub struct RingReader<'a, const N: usize> {
+    deque: &'a Deque<u8, N>,
+    idx: Cell<usize>,
+}
+
+impl<const N: usize> RingReader<'_, N> {
+    /// Number of bytes read so far.
+    pub fn position(&self) -> usize {
+        self.idx.get()
+    }
+}
+
+impl<const N: usize> Reader for RingReader<'_, N> {
+    fn read(&self, into: &mut [u8]) -> Result<NonZeroUsize, DidntRead> {
+        let to_read = core::cmp::min(into.len(), self.remaining());
+        let read = NonZeroUsize::new(to_read).ok_or(DidntRead)?;
+        self.read_exact(&mut into[..to_read])?;
+        Ok(read)
+    }
+
+    fn read_exact(&self, into: &mut [u8]) -> Result<(), DidntRead> {
+        if into.len() > self.remaining() {
+            return Err(DidntRead);
+        }
+        let idx = self.idx.get();
+        for (dst, src) in into.iter_mut().zip(self.deque.iter().skip(idx)) {
+            *dst = *src;
+        }
+        self.idx.set(idx + into.len());
+        Ok(())
+    }
+
+    fn remaining(&self) -> usize {
+        self.deque.len() - self.idx.get()
+    }
+
+    fn read_slice_in_place(&self, len: usize) -> Result<&[u8], DidntRead> {
+        if len > self.remaining() {
+            return Err(DidntRead);
+        }
+        let idx = self.idx.get();
+        let (front, back) = self.deque.as_slices();
+        let slice = if idx + len <= front.len() {
+            &front[idx..idx + len]
+        } else if idx >= front.len() {
+            &back[idx - front.len()..idx - front.len() + len]
+        } else {
+            return Err(DidntRead);
+        };
+        self.idx.set(idx + len);
         Ok(slice)
     }
+
+    fn skip(&self, n: usize) -> Result<(), DidntRead> {
+        if n > self.remaining() {
+            return Err(DidntRead);
+        }
+        self.idx.set(self.idx.get() + n);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        transport::{init::InitSyn, TransportBody, TransportMessage},
+        whatami::WhatAmI,
+        ZenohID,
+    };
+
+    #[test]
+    fn zvec_is_empty_until_written() {
+        let mut vec = ZVec::new();
+        assert!(vec.is_empty());
+
+        vec.write_exact(&[0x01, 0x02]).unwrap();
+        assert!(!vec.is_empty());
+        assert!(vec.iter().eq(&[0x01, 0x02]));
+        assert_eq!((&vec).into_iter().count(), 2);
+
+        vec.clear();
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn zvec_remaining_shrinks_as_bytes_are_written() {
+        let mut vec = ZVec::new();
+        assert_eq!(vec.capacity(), Z_MAX_MTU);
+        assert_eq!(vec.remaining(), Z_MAX_MTU);
+
+        vec.write_exact(&[0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(vec.remaining(), Z_MAX_MTU - 3);
+
+        vec.write_exact(&[0xff; Z_MAX_MTU - 3]).unwrap();
+        assert_eq!(vec.remaining(), 0);
+        assert_eq!(vec.capacity(), Z_MAX_MTU);
+
+        vec.clear();
+        assert_eq!(vec.remaining(), Z_MAX_MTU);
+    }
+
+    #[test]
+    fn zvec_reports_the_size_it_would_need() {
+        let mut vec = ZVec::new();
+        vec.write_exact(&[0xff; Z_MAX_MTU - 1]).unwrap();
+
+        assert_eq!(
+            vec.write_exact(&[0x01, 0x02]),
+            Err(WriteError::CapacityExceeded {
+                needed: Z_MAX_MTU + 1,
+                capacity: Z_MAX_MTU
+            })
+        );
+        assert_eq!(vec.len(), Z_MAX_MTU - 1);
+
+        let mut buf = [0u8; 4];
+        let mut writer = SliceWriter::new(&mut buf);
+        writer.write_exact(&[0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(
+            writer.write_u16_le(0x0405),
+            Err(WriteError::CapacityExceeded {
+                needed: 5,
+                capacity: 4
+            })
+        );
+        assert_eq!(writer.as_slice(), [0x01, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn multi_byte_helpers_are_little_endian() {
+        let mut vec = ZVec::new();
+        vec.write_u16_le(0x0800).unwrap();
+        vec.write_u32_le(0xdead_beef).unwrap();
+        assert_eq!(
+            vec.as_slice(),
+            [
+                &0x0800u16.to_le_bytes()[..],
+                &0xdead_beefu32.to_le_bytes()[..]
+            ]
+            .concat()
+        );
+        assert_eq!(vec.as_slice(), [0x00, 0x08, 0xef, 0xbe, 0xad, 0xde]);
+
+        let reader = SliceReader::new(vec.as_slice());
+        assert_eq!(reader.read_u16_le().unwrap(), 0x0800);
+        assert_eq!(reader.read_u32_le().unwrap(), 0xdead_beef);
+        assert!(reader.read_u16_le().is_err());
+    }
+
+    #[test]
+    fn skip_lands_on_the_following_byte() {
+        let bytes = [0x01, 0x02, 0x03, 0x04, 0x05];
+
+        let reader = SliceReader::new(&bytes);
+        reader.skip(4).unwrap();
+        assert_eq!(reader.read_u8().unwrap(), 0x05);
+        assert!(reader.skip(1).is_err());
+
+        let mut vec = ZVec::new();
+        vec.write_exact(&bytes).unwrap();
+        let reader = vec.extract_slice(bytes.len()).unwrap();
+        assert!(reader.skip(6).is_err());
+        reader.skip(4).unwrap();
+        assert_eq!(reader.read_u8().unwrap(), 0x05);
+    }
+
+    #[test]
+    fn ring_decodes_a_message_once_complete() {
+        let mut msg = ZVec::new();
+        InitSyn::message(WhatAmI::Client, ZenohID::from(0x49))
+            .encode(&mut msg)
+            .unwrap();
+        let (head, tail) = msg.as_slice().split_at(3);
+
+        // Leftovers of an earlier message push the new one across the wrap
+        let mut ring = RingBuf::<16>::new();
+        ring.writer().write_exact(&[0xff; 12]).unwrap();
+        ring.consume(12);
+
+        ring.writer().write_exact(head).unwrap();
+        assert!(TransportMessage::decode(&ring.reader()).is_err());
+        assert_eq!(ring.len(), head.len());
+
+        ring.writer().write_exact(tail).unwrap();
+        let reader = ring.reader();
+        let decoded = TransportMessage::decode(&reader).unwrap();
+        assert!(
+            matches!(decoded.body, TransportBody::InitSyn(ism) if ism.zid == ZenohID::from(0x49))
+        );
+        let read = reader.position();
+        ring.consume(read);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn ring_refuses_writes_past_its_capacity() {
+        let mut ring = RingBuf::<4>::new();
+        ring.writer().write_exact(&[0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(
+            ring.writer().write_exact(&[0x04, 0x05]),
+            Err(WriteError::CapacityExceeded {
+                needed: 5,
+                capacity: 4
+            })
+        );
+        assert_eq!(ring.len(), 3);
+    }
 }
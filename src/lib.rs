@@ -1,50 +1,1540 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+#![cfg_attr(not(test), no_main)]
 
-use link::{Endpoint, LinkIntf};
-use protocol::{whatami::WhatAmI, ZenohID};
+use heapless::Vec;
+use link::{Endpoint, LinkCapabilities, LinkIntf};
+use protocol::{
+    encoding::Encoding,
+    keyexpr::{self, KeyExpr},
+    network::{
+        declare::{Declare, DeclareBody, DeclareKeyExpr, DeclareSubscriber},
+        push::{Push, PushBody},
+        request::Request,
+        response::ResponseBody,
+        NetworkMessage,
+    },
+    priority::Priority,
+    reliability::Reliability,
+    timestamp::Timestamp,
+    transport::close::CloseReason,
+    whatami::WhatAmI,
+    ProtocolError, ZenohID,
+};
 use thiserror::Error;
-use transport::Transport;
+use transport::{Transport, TransportError, TxQueue, UnicastParams};
 
 mod iobuf;
 pub mod link;
 pub mod protocol;
 pub mod transport;
 
+pub use iobuf::{
+    DidntRead, Reader, RingBuf, RingReader, RingWriter, SliceReader, SliceWriter, WriteError,
+    Writer,
+};
+
 const Z_BATCH_UNICAST_SIZE: u16 = 2048;
+const Z_BATCH_MULTICAST_SIZE: u16 = 2048;
 const Z_MAX_MTU: usize = 2048;
 const Z_PROTO_VERSION: u8 = 0x09;
+const Z_PATCH: u8 = 0;
 const Z_SN_RESOLUTION: u8 = 0x02;
 const Z_REQ_RESOLUTION: u8 = 0x02;
 const Z_TRANSPORT_LEASE: u32 = 10000;
+const Z_JOIN_INTERVAL: u32 = 2500;
+const Z_MULTICAST_MAX_PEERS: usize = 8;
+const Z_MAX_SUBSCRIBERS: usize = 8;
+const Z_MAX_KEYEXPRS: usize = 8;
+const Z_MAX_KEYEXPR_SIZE: usize = 64;
+const Z_MAX_COOKIE_SIZE: usize = 256;
+const Z_CONGESTION_RETRIES: u8 = 3;
+const Z_CONGESTION_BACKOFF_MS: u32 = 10;
+const Z_TX_QUEUE_SIZE: usize = 8;
+const Z_TX_MSG_SIZE: usize = 256;
+const Z_MAX_REPLIES: usize = 4;
+const Z_MAX_REPLY_KEYEXPR: usize = 64;
+const Z_MAX_REPLY_SIZE: usize = 256;
+const Z_QUERY_MAX_READS: usize = 8;
+
+// Batches and link MTUs all have to fit in the transport buffers of
+// Z_MAX_MTU bytes, editing one of these constants must not break that.
+const _: () = {
+    assert!(Z_BATCH_UNICAST_SIZE as usize <= Z_MAX_MTU);
+    assert!(Z_BATCH_MULTICAST_SIZE as usize <= Z_MAX_MTU);
+    assert!(link::serial::SERIAL_MAX_MTU <= Z_MAX_MTU);
+    assert!(link::raweth::RAWETH_MTU <= Z_MAX_MTU);
+    assert!(Z_TX_MSG_SIZE <= Z_MAX_MTU);
+};
 
 #[derive(Debug, Error)]
 pub enum SessionError {
     #[error("Transport Error")]
-    TransportError(#[from] crate::transport::TransportError)
+    TransportError(#[from] crate::transport::TransportError),
+    #[error("Protocol Error")]
+    ProtocolError(#[from] ProtocolError),
+    #[error("Subscriber table full")]
+    SubscriberTableFull,
+    /// Every key expression mapping is kept to be declared again on
+    /// [`Session::reconnect`], at most `Z_MAX_KEYEXPRS` of them.
+    #[error("Key expression table full")]
+    KeyExprTableFull,
+    /// ID 0 is the empty scope, a key expression cannot be mapped to it.
+    #[error("Key expression ID 0 is reserved")]
+    ReservedKeyExprId,
+    /// More replies than [`Replies`] holds, or one too large to be copied,
+    /// were received for a query.
+    #[error("Reply table full")]
+    ReplyTableFull,
+    /// A reply was scoped by a key expression ID the session never declared,
+    /// so its key cannot be rebuilt.
+    #[error("Unknown key expression ID {0}")]
+    UnknownKeyExprId(u16),
+    /// No final response arrived within the reads a query is allowed.
+    #[error("Query timed out")]
+    QueryTimeout,
+    /// Every endpoint given to [`open_any`] failed, `last` is the error of the
+    /// final attempt.
+    #[error("No endpoint reachable after {attempts} attempts")]
+    NoEndpointReachable {
+        attempts: usize,
+        last: Option<crate::transport::TransportError>,
+    },
 }
 
 pub struct Config {
     pub id: ZenohID,
     pub mode: WhatAmI,
+    /// Messages are flushed at the latest this many milliseconds after the
+    /// first one got batched, 0 sends every message right away.
+    pub batch_window_ms: u32,
+    /// Invoked with the negotiated parameters once a unicast handshake
+    /// completes, e.g. to reconfigure the physical link.
+    pub on_established: Option<fn(&UnicastParams)>,
+    /// Aborts the unicast handshake once more than this many bytes have been
+    /// sent and received in total.
+    pub max_handshake_bytes: Option<usize>,
+    /// Asks the peer for a QoS transport, so puts can use other priorities.
+    pub qos: bool,
+    /// Lease announced to the peer, in milliseconds.
+    pub lease_ms: u32,
+    /// Largest batch proposed to the peer, the link MTU still applies.
+    pub batch_size: u16,
+    /// Seeds the initial sequence number.
+    pub sn_seed: u64,
+    /// Current time as NTP64, for [`Session::timestamp`].
+    pub clock: Option<fn() -> u64>,
+    /// Blocks for the given number of milliseconds, to back off between the
+    /// retries of a [`CongestionControl::Block`] put.
+    pub delay_ms: Option<fn(u32)>,
+    /// Bounds the reliable frames in flight by half the SN space. Nothing
+    /// in the protocol acknowledges frames, the application then releases
+    /// them with [`Session::acknowledge`].
+    pub ack_window: bool,
 }
 
 impl Config {
+    /// Shorthand for `Config::builder(id, mode).build()`.
     pub fn new(id: ZenohID, mode: WhatAmI) -> Self {
-        Config { id, mode }
+        Config::builder(id, mode).build()
+    }
+
+    pub fn builder(id: ZenohID, mode: WhatAmI) -> ConfigBuilder {
+        ConfigBuilder {
+            cfg: Config {
+                id,
+                mode,
+                batch_window_ms: 0,
+                on_established: None,
+                max_handshake_bytes: None,
+                qos: false,
+                lease_ms: Z_TRANSPORT_LEASE,
+                batch_size: Z_BATCH_UNICAST_SIZE,
+                sn_seed: 0,
+                clock: None,
+                delay_ms: None,
+                ack_window: false,
+            },
+        }
+    }
+}
+
+/// Builds a [`Config`], every setting left out keeps its default.
+pub struct ConfigBuilder {
+    cfg: Config,
+}
+
+impl ConfigBuilder {
+    pub fn batch_window_ms(mut self, batch_window_ms: u32) -> Self {
+        self.cfg.batch_window_ms = batch_window_ms;
+        self
+    }
+
+    pub fn on_established(mut self, on_established: fn(&UnicastParams)) -> Self {
+        self.cfg.on_established = Some(on_established);
+        self
+    }
+
+    pub fn max_handshake_bytes(mut self, max_handshake_bytes: usize) -> Self {
+        self.cfg.max_handshake_bytes = Some(max_handshake_bytes);
+        self
+    }
+
+    pub fn qos(mut self, qos: bool) -> Self {
+        self.cfg.qos = qos;
+        self
+    }
+
+    pub fn lease_ms(mut self, lease_ms: u32) -> Self {
+        self.cfg.lease_ms = lease_ms;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: u16) -> Self {
+        self.cfg.batch_size = batch_size;
+        self
+    }
+
+    pub fn sn_seed(mut self, sn_seed: u64) -> Self {
+        self.cfg.sn_seed = sn_seed;
+        self
+    }
+
+    pub fn clock(mut self, clock: fn() -> u64) -> Self {
+        self.cfg.clock = Some(clock);
+        self
+    }
+
+    pub fn delay_ms(mut self, delay_ms: fn(u32)) -> Self {
+        self.cfg.delay_ms = Some(delay_ms);
+        self
+    }
+
+    pub fn ack_window(mut self, ack_window: bool) -> Self {
+        self.cfg.ack_window = ack_window;
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.cfg
+    }
+}
+
+/// Per-put settings for [`Session::put_with_options`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PutOptions {
+    /// Anything but the default priority needs QoS to have been negotiated.
+    pub priority: Priority,
+    pub congestion_control: CongestionControl,
+    /// Sets the R flag of the Frame carrying the put.
+    pub reliability: Reliability,
+}
+
+/// What a put does when the link refuses to take more data.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CongestionControl {
+    /// Give up right away and report the error.
+    #[default]
+    Drop,
+    /// Retry up to `Z_CONGESTION_RETRIES` times on transient link errors,
+    /// `Z_CONGESTION_BACKOFF_MS` apart, before reporting the error.
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SessionInfo {
+    pub zid: ZenohID,
+    pub peer_zid: Option<ZenohID>,
+    pub peer_whatami: Option<WhatAmI>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SubscriberId(pub u32);
+
+/// Publishes on a key expression declared once through
+/// [`Session::declare_publisher`], so its puts only carry the numeric ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Publisher {
+    expr_id: u16,
+}
+
+impl Publisher {
+    pub fn put<L: LinkIntf>(
+        &self,
+        session: &mut Session<L>,
+        payload: &[u8],
+    ) -> Result<(), SessionError> {
+        let msg = NetworkMessage::Push(Push::new(KeyExpr::with_id(self.expr_id, ""), payload));
+        session
+            .transport
+            .queue(&msg, Priority::default(), Reliability::default())?;
+        Ok(())
     }
 }
 
+/// A received sample handed to subscriber callbacks. Both slices borrow the
+/// transport's receive buffer, so they are only valid during the callback
+/// and must be copied out to be kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sample<'a> {
+    pub keyexpr: &'a str,
+    pub payload: &'a [u8],
+}
 
-pub struct Session {
+/// A reply received by [`Session::get`], copied out of the receive buffer
+/// since the next read overwrites it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub keyexpr: heapless::String<Z_MAX_REPLY_KEYEXPR>,
+    pub payload: Vec<u8, Z_MAX_REPLY_SIZE>,
+}
+
+pub type Replies = Vec<Reply, Z_MAX_REPLIES>;
+
+struct Subscriber {
+    id: u32,
+    keyexpr: KeyExpr<'static>,
+    callback: fn(&Sample),
+}
 
+pub struct Session<L> {
+    transport: Transport<L>,
+    zid: ZenohID,
+    next_id: u32,
+    next_expr_id: u16,
+    subscribers: Vec<Subscriber, Z_MAX_SUBSCRIBERS>,
+    keyexprs: Vec<(u16, heapless::String<Z_MAX_KEYEXPR_SIZE>), Z_MAX_KEYEXPRS>,
+    tx_queue: TxQueue,
+    clock: Option<fn() -> u64>,
+    delay_ms: Option<fn(u32)>,
 }
 
-pub fn open<L: LinkIntf, E: Endpoint<L = L>>(ep: E, cfg: &Config) -> Result<Session, SessionError> {
-    let _t = Transport::new(ep, cfg)?;
-    Ok(Session {})
+pub fn open<L: LinkIntf, E: Endpoint<L = L>>(
+    ep: E,
+    cfg: &Config,
+) -> Result<Session<L>, SessionError> {
+    let transport = Transport::new(ep, cfg)?;
+    Ok(Session {
+        transport,
+        zid: cfg.id,
+        next_id: 1,
+        next_expr_id: 1,
+        subscribers: Vec::new(),
+        keyexprs: Vec::new(),
+        tx_queue: TxQueue::new(),
+        clock: cfg.clock,
+        delay_ms: cfg.delay_ms,
+    })
+}
+
+/// Tries every endpoint of `endpoints` in order and returns the session of
+/// the first one that opens.
+pub fn open_any<L: LinkIntf, E: Endpoint<L = L>, I: IntoIterator<Item = E>>(
+    endpoints: I,
+    cfg: &Config,
+) -> Result<Session<L>, SessionError> {
+    let mut attempts = 0;
+    let mut last = None;
+
+    for ep in endpoints {
+        attempts += 1;
+        match open(ep, cfg) {
+            Ok(session) => return Ok(session),
+            Err(SessionError::TransportError(e)) => {
+                #[cfg(feature = "defmt")]
+                defmt::warn!("Endpoint {} failed", attempts);
+                last = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(SessionError::NoEndpointReachable { attempts, last })
+}
+
+impl<L: LinkIntf> Session<L> {
+    pub fn info(&self) -> SessionInfo {
+        let peer = self.transport.peer();
+        SessionInfo {
+            zid: self.zid,
+            peer_zid: peer.map(|(zid, _)| zid),
+            peer_whatami: peer.map(|(_, whatami)| whatami),
+        }
+    }
+
+    /// Appends a sample to the pending batch, it goes out with the batch on
+    /// [`Session::flush`], once the batch is full or its window elapsed, or
+    /// along with the next message sent right away.
+    pub fn put(&mut self, keyexpr: &str, payload: &[u8]) -> Result<(), SessionError> {
+        let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from(keyexpr)?, payload));
+        self.transport
+            .queue(&msg, Priority::default(), Reliability::default())?;
+        Ok(())
+    }
+
+    /// Like [`Session::put`], tagging the payload with `encoding`.
+    pub fn put_with_encoding(
+        &mut self,
+        keyexpr: &str,
+        payload: &[u8],
+        encoding: Encoding,
+    ) -> Result<(), SessionError> {
+        let msg = NetworkMessage::Push(Push::with_encoding(
+            KeyExpr::try_from(keyexpr)?,
+            payload,
+            encoding,
+        ));
+        self.transport
+            .queue(&msg, Priority::default(), Reliability::default())?;
+        Ok(())
+    }
+
+    /// A timestamp of now from this session, `None` without a
+    /// [`Config::clock`].
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        self.clock.map(|clock| Timestamp::new(clock(), self.zid))
+    }
+
+    /// Like [`Session::put`], stamping the sample with `ts`, e.g. from
+    /// [`Session::timestamp`].
+    pub fn put_with_timestamp(
+        &mut self,
+        keyexpr: &str,
+        payload: &[u8],
+        ts: Timestamp,
+    ) -> Result<(), SessionError> {
+        let msg = NetworkMessage::Push(Push::with_timestamp(
+            KeyExpr::try_from(keyexpr)?,
+            payload,
+            ts,
+        ));
+        self.transport
+            .queue(&msg, Priority::default(), Reliability::default())?;
+        Ok(())
+    }
+
+    pub fn put_with_options(
+        &mut self,
+        keyexpr: &str,
+        payload: &[u8],
+        options: &PutOptions,
+    ) -> Result<(), SessionError> {
+        let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from(keyexpr)?, payload));
+
+        let mut retries = match options.congestion_control {
+            CongestionControl::Drop => 0,
+            CongestionControl::Block => Z_CONGESTION_RETRIES,
+        };
+        loop {
+            match self
+                .transport
+                .queue(&msg, options.priority, options.reliability)
+            {
+                Err(TransportError::LinkError(e)) if e.is_transient() && retries > 0 => {
+                    retries -= 1;
+                    if let Some(delay_ms) = self.delay_ms {
+                        delay_ms(Z_CONGESTION_BACKOFF_MS);
+                    }
+                }
+                res => return Ok(res?),
+            }
+        }
+    }
+
+    pub fn declare_subscriber(&mut self, keyexpr: &str) -> Result<SubscriberId, SessionError> {
+        let id = self.next_id;
+        self.send_subscriber(id, KeyExpr::try_from(keyexpr)?)?;
+        self.next_id += 1;
+
+        Ok(SubscriberId(id))
+    }
+
+    fn send_subscriber(&mut self, id: u32, keyexpr: KeyExpr) -> Result<(), SessionError> {
+        let msg = NetworkMessage::Declare(Declare::new(DeclareBody::DeclareSubscriber(
+            DeclareSubscriber { id, keyexpr },
+        )));
+        self.transport.send(&msg)?;
+        Ok(())
+    }
+
+    /// Maps `keyexpr` to a numeric ID on the peer so it can be referred to
+    /// without resending the whole string. The mapping is declared again on
+    /// [`Session::reconnect`].
+    pub fn declare_keyexpr(&mut self, keyexpr: &str) -> Result<u16, SessionError> {
+        let id = self.next_expr_id;
+        self.map_keyexpr(id, keyexpr)?;
+        self.next_expr_id += 1;
+
+        Ok(id)
+    }
+
+    /// Declares every `(id, keyexpr)` mapping of `mappings` at once, packing the
+    /// Declare messages into as few frames as possible. Subsequent calls to
+    /// [`Session::declare_keyexpr`] allocate IDs past the highest one given here.
+    ///
+    /// On an error the mappings declared before the failing one are still
+    /// sent.
+    pub fn declare_keyexprs(&mut self, mappings: &[(u16, &str)]) -> Result<(), SessionError> {
+        if mappings.iter().any(|&(id, _)| id == 0) {
+            return Err(SessionError::ReservedKeyExprId);
+        }
+
+        self.transport.begin_batch();
+        let declared = self.map_keyexprs(mappings);
+        // The batch is closed on every path, later sends would be held back
+        let flushed = self.transport.flush_batch();
+        declared?;
+        flushed?;
+
+        Ok(())
+    }
+
+    fn map_keyexprs(&mut self, mappings: &[(u16, &str)]) -> Result<(), SessionError> {
+        for &(id, keyexpr) in mappings {
+            self.map_keyexpr(id, keyexpr)?;
+            self.next_expr_id = self.next_expr_id.max(id.saturating_add(1));
+        }
+
+        Ok(())
+    }
+
+    /// Declares the mapping of `id` to `keyexpr` and records it, replacing
+    /// any earlier mapping of `id`.
+    fn map_keyexpr(&mut self, id: u16, keyexpr: &str) -> Result<(), SessionError> {
+        let slot = self.keyexprs.iter().position(|(mapped, _)| *mapped == id);
+        if slot.is_none() && self.keyexprs.is_full() {
+            return Err(SessionError::KeyExprTableFull);
+        }
+        let owned =
+            heapless::String::try_from(keyexpr).map_err(|_| ProtocolError::KeyExprTooLong)?;
+
+        self.send_keyexpr(id, KeyExpr::try_from(keyexpr)?)?;
+        match slot {
+            Some(i) => self.keyexprs[i].1 = owned,
+            None => {
+                let _ = self.keyexprs.push((id, owned));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_keyexpr(&mut self, id: u16, keyexpr: KeyExpr) -> Result<(), SessionError> {
+        let msg =
+            NetworkMessage::Declare(Declare::new(DeclareBody::DeclareKeyExpr(DeclareKeyExpr {
+                id,
+                keyexpr,
+            })));
+        self.transport.send(&msg)?;
+        Ok(())
+    }
+
+    /// Declares a subscriber on `keyexpr` whose `callback` is invoked with
+    /// every matching [`Sample`] received through [`Session::read`].
+    pub fn subscribe(
+        &mut self,
+        keyexpr: &'static str,
+        callback: fn(&Sample),
+    ) -> Result<SubscriberId, SessionError> {
+        if self.subscribers.is_full() {
+            return Err(SessionError::SubscriberTableFull);
+        }
+
+        let id = self.declare_subscriber(keyexpr)?;
+        let _ = self.subscribers.push(Subscriber {
+            id: id.0,
+            keyexpr: KeyExpr::try_from(keyexpr)?,
+            callback,
+        });
+
+        Ok(id)
+    }
+
+    /// Declares `keyexpr` under a numeric ID and returns a [`Publisher`] putting
+    /// on it. The mapping is declared again on [`Session::reconnect`].
+    pub fn declare_publisher(&mut self, keyexpr: &str) -> Result<Publisher, SessionError> {
+        let expr_id = self.declare_keyexpr(keyexpr)?;
+        Ok(Publisher { expr_id })
+    }
+
+    /// Reads one batch from the transport and dispatches the samples it
+    /// carries to the matching subscribers.
+    pub fn read(&mut self) -> Result<(), SessionError> {
+        let subscribers = &self.subscribers;
+
+        self.transport.recv(|msg| {
+            if let NetworkMessage::Push(push) = msg {
+                dispatch(subscribers, push);
+            }
+        })?;
+
+        Ok(())
+    }
+
+    /// Sends a query on `keyexpr` with the given `parameters` and reads until
+    /// the matching final response, returning every reply received. Samples
+    /// received meanwhile still reach the subscribers.
+    ///
+    /// Gives up with [`SessionError::QueryTimeout`] after `Z_QUERY_MAX_READS`
+    /// batches without the final response. Reply keys scoped by a mapping are
+    /// expanded against the ones declared through [`Session::declare_keyexpr`].
+    pub fn get(&mut self, keyexpr: &str, parameters: &str) -> Result<Replies, SessionError> {
+        let id = self.next_id;
+        let msg =
+            NetworkMessage::Request(Request::new(id, KeyExpr::try_from(keyexpr)?, parameters));
+        self.transport.send(&msg)?;
+        self.next_id += 1;
+
+        let subscribers = &self.subscribers;
+        let keyexprs = &self.keyexprs;
+        let mut replies = Replies::new();
+        let mut overflow = false;
+        let mut unknown = None;
+        let mut done = false;
+        for _ in 0..Z_QUERY_MAX_READS {
+            self.transport.recv(|msg| match msg {
+                NetworkMessage::Response(response) if response.request_id == id => {
+                    let ResponseBody::Reply(put) = &response.body;
+                    let Some(prefix) = scope_prefix(keyexprs, response.keyexpr.id) else {
+                        unknown = Some(response.keyexpr.id);
+                        return;
+                    };
+                    let mut keyexpr = heapless::String::new();
+                    let reply = keyexpr
+                        .push_str(prefix)
+                        .and_then(|_| keyexpr.push_str(response.keyexpr.suffix))
+                        .ok()
+                        .zip(Vec::from_slice(put.payload).ok())
+                        .map(|(_, payload)| Reply { keyexpr, payload });
+                    // Keep reading up to the final response so the remaining
+                    // replies do not leak into the next read.
+                    overflow |= reply.is_none_or(|reply| replies.push(reply).is_err());
+                }
+                NetworkMessage::ResponseFinal(fin) if fin.request_id == id => done = true,
+                NetworkMessage::Push(push) => dispatch(subscribers, push),
+                _ => {}
+            })?;
+
+            if let (true, Some(unknown)) = (done, unknown) {
+                return Err(SessionError::UnknownKeyExprId(unknown));
+            }
+            if done && overflow {
+                return Err(SessionError::ReplyTableFull);
+            }
+            if done {
+                return Ok(replies);
+            }
+        }
+
+        Err(SessionError::QueryTimeout)
+    }
+
+    /// Transport, flow and reliability of the link the session runs over.
+    pub fn link_capabilities(&self) -> LinkCapabilities {
+        self.transport.link_capabilities()
+    }
+
+    /// Re-establishes the session after the link dropped, the subscribers
+    /// registered through [`Session::subscribe`] and the key expression
+    /// mappings are declared again under their original IDs.
+    pub fn reconnect(&mut self, cfg: &Config) -> Result<(), SessionError> {
+        self.transport.reconnect(cfg)?;
+
+        self.transport.begin_batch();
+        let declared = self.declare_again();
+        // The batch is closed on every path, later sends would be held back
+        let flushed = self.transport.flush_batch();
+        declared?;
+        flushed?;
+
+        Ok(())
+    }
+
+    /// Sends the Declare of every subscriber and key expression mapping kept
+    /// by the session.
+    fn declare_again(&mut self) -> Result<(), SessionError> {
+        for i in 0..self.subscribers.len() {
+            let Subscriber { id, keyexpr, .. } = self.subscribers[i];
+            self.send_subscriber(id, keyexpr)?;
+        }
+        for i in 0..self.keyexprs.len() {
+            let (id, keyexpr) = self.keyexprs[i].clone();
+            self.send_keyexpr(id, KeyExpr::new(&keyexpr))?;
+        }
+
+        Ok(())
+    }
+
+    /// Reason given by the peer in the last Close received through
+    /// [`Session::read`] or [`Session::get`], for diagnostics.
+    pub fn last_close_reason(&self) -> Option<CloseReason> {
+        self.transport.last_close_reason()
+    }
+
+    /// Queues an encoded network message for [`Session::pump_tx`] without
+    /// touching the link.
+    pub fn try_enqueue(&mut self, msg: &[u8]) -> Result<(), SessionError> {
+        self.tx_queue.try_enqueue(msg)?;
+        Ok(())
+    }
+
+    /// Frames every message queued by [`Session::try_enqueue`] and sends them.
+    pub fn pump_tx(&mut self) -> Result<(), SessionError> {
+        while let Some(msg) = self.tx_queue.dequeue() {
+            self.transport
+                .queue_encoded(&msg, Priority::default(), Reliability::default())?;
+        }
+        self.transport.flush()?;
+        Ok(())
+    }
+
+    /// Releases `frames` reliable frames from the window kept with
+    /// [`Config::ack_window`], once they are known to have arrived.
+    pub fn acknowledge(&mut self, frames: u64) {
+        self.transport.acknowledge(frames);
+    }
+
+    /// Holds back subsequent messages until [`Session::flush_batch`] so they
+    /// share as few frames as possible.
+    pub fn begin_batch(&mut self) {
+        self.transport.begin_batch();
+    }
+
+    pub fn flush_batch(&mut self) -> Result<(), SessionError> {
+        self.transport.flush_batch()?;
+        Ok(())
+    }
+
+    /// Sends the samples batched so far.
+    pub fn flush(&mut self) -> Result<(), SessionError> {
+        self.transport.flush()?;
+        Ok(())
+    }
+
+    /// Advances the session timers by `elapsed_ms`, flushing the pending batch
+    /// once the configured window has elapsed.
+    pub fn tick(&mut self, elapsed_ms: u32) -> Result<(), SessionError> {
+        self.transport.tick(elapsed_ms)?;
+        Ok(())
+    }
+
+    /// Closes the session with `reason`, pending data is flushed before the
+    /// Close is sent to the peer.
+    pub fn close(mut self, reason: CloseReason) -> Result<(), SessionError> {
+        self.transport.flush_batch()?;
+        self.transport.close(reason)?;
+        Ok(())
+    }
+
+    /// Gracefully tears the session down: pending data is flushed, a Close is
+    /// sent to the peer and the link is given at most `timeout_ms` to drain.
+    pub fn shutdown(mut self, timeout_ms: u32) -> Result<(), SessionError> {
+        self.transport.flush_batch()?;
+        self.transport.close(CloseReason::Generic)?;
+        self.transport.drain(timeout_ms)?;
+        Ok(())
+    }
 }
 
-impl Session {
-    
-}
\ No newline at end of file
+/// Key expression mapped to `id` by the session, ID 0 being the empty scope.
+fn scope_prefix(keyexprs: &[(u16, heapless::String<Z_MAX_KEYEXPR_SIZE>)], id: u16) -> Option<&str> {
+    if id == 0 {
+        return Some("");
+    }
+    keyexprs
+        .iter()
+        .find(|(mapped, _)| *mapped == id)
+        .map(|(_, keyexpr)| keyexpr.as_str())
+}
+
+fn dispatch(subscribers: &[Subscriber], push: &Push) {
+    // Only full key expressions are matched, mappings declared by the
+    // peer are not tracked.
+    if push.keyexpr.id != 0 {
+        return;
+    }
+
+    let PushBody::Put(put) = &push.body;
+    let sample = Sample {
+        keyexpr: push.keyexpr.suffix,
+        payload: put.payload,
+    };
+    for sub in subscribers
+        .iter()
+        .filter(|sub| keyexpr::intersects(sub.keyexpr.suffix, sample.keyexpr))
+    {
+        (sub.callback)(&sample);
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::link::mock::{MockIo, MockLink};
+    use crate::protocol::network::response::{Response, ResponseFinal};
+    use crate::protocol::transport::{init::InitSyn, TransportBody, TransportMessage};
+
+    fn open_mock(io: &RefCell<MockIo>) -> Session<MockLink<'_>> {
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let cfg = Config::new(ZenohID::from(0x49), WhatAmI::Client);
+        open(MockLink::unicast(io), &cfg).unwrap()
+    }
+
+    #[test]
+    fn open_any_falls_over_to_the_next_endpoint() {
+        let bad = RefCell::new(MockIo::new());
+        bad.borrow_mut().fail_sends(1);
+        let good = RefCell::new(MockIo::new());
+        good.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let cfg = Config::new(ZenohID::from(0x49), WhatAmI::Client);
+
+        let session = open_any([MockLink::unicast(&bad), MockLink::unicast(&good)], &cfg).unwrap();
+        assert_eq!(session.info().peer_zid, Some(ZenohID::from(0xbeef)));
+        assert!(bad.borrow().outbound().is_empty());
+        assert_eq!(good.borrow().pending_inbound(), 0);
+
+        bad.borrow_mut().fail_sends(2);
+        assert!(matches!(
+            open_any([MockLink::unicast(&bad), MockLink::unicast(&bad)], &cfg),
+            Err(SessionError::NoEndpointReachable {
+                attempts: 2,
+                last: Some(TransportError::LinkError(link::LinkError::IoError)),
+            })
+        ));
+    }
+
+    #[test]
+    fn link_capabilities_come_from_the_link() {
+        let io = RefCell::new(MockIo::new());
+        let cfg = Config::new(ZenohID::from(0x49), WhatAmI::Client);
+        let session = open(MockLink::multicast(&io), &cfg).unwrap();
+
+        let cap = session.link_capabilities();
+        assert_eq!(cap.transport(), link::TransportCap::Multicast);
+        assert_eq!(cap.flow(), link::TransportFlow::DATAGRAM);
+        assert!(cap.reliable());
+    }
+
+    #[test]
+    fn info_reports_the_peer_role() {
+        let io = RefCell::new(MockIo::new());
+        let session = open_mock(&io);
+        let info = session.info();
+        assert_eq!(info.peer_zid, Some(ZenohID::from(0xbeef)));
+        assert_eq!(info.peer_whatami, Some(WhatAmI::Router));
+    }
+
+    /// Numeric scope of the Push carried by the Frame `frame`, which must not
+    /// have a suffix.
+    fn push_keyexpr_id(frame: &[u8]) -> u16 {
+        let reader = SliceReader::new(frame);
+        let msg = TransportMessage::decode(&reader).unwrap();
+        assert!(matches!(msg.body, TransportBody::Frame(_)));
+        match NetworkMessage::decode(&reader).unwrap() {
+            NetworkMessage::Push(push) => {
+                assert!(!push.keyexpr.has_suffix());
+                push.keyexpr.id
+            }
+            _ => panic!("expected a Push"),
+        }
+    }
+
+    #[test]
+    fn publisher_reuses_its_keyexpr_id() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+
+        let publisher = session.declare_publisher("demo/example").unwrap();
+        for payload in [b"one", b"two"] {
+            publisher.put(&mut session, payload).unwrap();
+            session.flush().unwrap();
+        }
+
+        let io = io.borrow();
+        let sent = io.outbound();
+        assert_eq!(push_keyexpr_id(&sent[sent.len() - 2]), 1);
+        assert_eq!(push_keyexpr_id(&sent[sent.len() - 1]), 1);
+    }
+
+    #[test]
+    fn puts_outlast_the_sn_window() {
+        let io = RefCell::new(MockIo::new());
+        let mut ack = InitSyn::ack(WhatAmI::Router, ZenohID::from(0xbeef), &[0xc0]);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            // 8-bit SNs, 64 reliable frames would fill the window
+            iam.seq_num_res = 0;
+        }
+        io.borrow_mut().push_message(&ack).unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        let cfg = Config::new(ZenohID::from(0x49), WhatAmI::Client);
+        let mut session = open(MockLink::unicast(&io), &cfg).unwrap();
+
+        for _ in 0..200 {
+            session.put("demo/a", b"x").unwrap();
+            session.flush().unwrap();
+            io.borrow_mut().clear_outbound();
+        }
+
+        // Opted into, the window holds until the application releases it
+        io.borrow_mut().push_message(&ack).unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        let cfg = Config::builder(ZenohID::from(0x49), WhatAmI::Client)
+            .ack_window(true)
+            .build();
+        let mut session = open(MockLink::unicast(&io), &cfg).unwrap();
+        for _ in 0..64 {
+            session.put("demo/a", b"x").unwrap();
+            session.flush().unwrap();
+            io.borrow_mut().clear_outbound();
+        }
+        assert!(matches!(
+            session.put("demo/a", b"x"),
+            Err(SessionError::TransportError(TransportError::WindowFull))
+        ));
+        session.acknowledge(1);
+        session.put("demo/a", b"x").unwrap();
+    }
+
+    #[test]
+    fn small_puts_share_a_frame() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        let sent = io.borrow().outbound().len();
+
+        session.put("demo/a", b"one").unwrap();
+        session.put("demo/b", b"two").unwrap();
+        assert_eq!(io.borrow().outbound().len(), sent);
+        session.flush().unwrap();
+
+        let io = io.borrow();
+        assert_eq!(io.outbound().len(), sent + 1);
+        let reader = SliceReader::new(io.outbound().last().unwrap());
+        let msg = TransportMessage::decode(&reader).unwrap();
+        assert!(matches!(msg.body, TransportBody::Frame(_)));
+        for payload in [b"one", b"two"] {
+            let NetworkMessage::Push(push) = NetworkMessage::decode(&reader).unwrap() else {
+                panic!("expected a Push");
+            };
+            let PushBody::Put(put) = &push.body;
+            assert_eq!(put.payload, payload);
+        }
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn queued_messages_are_pumped_into_a_frame() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+
+        for payload in [b"one", b"two", b"six"] {
+            let msg =
+                NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/tx").unwrap(), payload));
+            let mut buf = [0u8; 32];
+            let mut writer = SliceWriter::new(&mut buf);
+            msg.encode(&mut writer).unwrap();
+            let len = writer.len();
+            session.try_enqueue(&buf[..len]).unwrap();
+        }
+        let sent = io.borrow().outbound().len();
+        session.pump_tx().unwrap();
+
+        let io = io.borrow();
+        assert_eq!(io.outbound().len(), sent + 1);
+        let reader = SliceReader::new(io.outbound().last().unwrap());
+        let msg = TransportMessage::decode(&reader).unwrap();
+        assert!(matches!(msg.body, TransportBody::Frame(_)));
+        for payload in [b"one", b"two", b"six"] {
+            let NetworkMessage::Push(push) = NetworkMessage::decode(&reader).unwrap() else {
+                panic!("expected a Push");
+            };
+            let PushBody::Put(put) = &push.body;
+            assert_eq!(put.payload, payload);
+        }
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn samples_borrow_the_receive_buffer() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static PAYLOAD_AT: AtomicUsize = AtomicUsize::new(0);
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_sample(sample: &Sample) {
+            assert_eq!(sample.keyexpr, "demo/rx");
+            assert_eq!(sample.payload, b"hello");
+            PAYLOAD_AT.store(sample.payload.as_ptr() as usize, Ordering::Relaxed);
+            CALLS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut buf = [0u8; 32];
+        let mut writer = SliceWriter::new(&mut buf);
+        NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/rx").unwrap(), b"hello"))
+            .encode(&mut writer)
+            .unwrap();
+        let len = writer.len();
+
+        let subscribers = [Subscriber {
+            id: 1,
+            keyexpr: KeyExpr::try_from("demo/*").unwrap(),
+            callback: on_sample,
+        }];
+        let reader = SliceReader::new(&buf[..len]);
+        let NetworkMessage::Push(push) = NetworkMessage::decode(&reader).unwrap() else {
+            panic!("expected a Push");
+        };
+        dispatch(&subscribers, &push);
+
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        assert!(buf
+            .as_ptr_range()
+            .contains(&(PAYLOAD_AT.load(Ordering::Relaxed) as *const u8)));
+    }
+
+    #[test]
+    fn read_delivers_to_the_matching_subscriber_only() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static MATCHING: AtomicUsize = AtomicUsize::new(0);
+        static OTHER: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_matching(sample: &Sample) {
+            assert_eq!(sample.payload, b"hello");
+            MATCHING.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_other(_: &Sample) {
+            OTHER.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        session.subscribe("demo/a", on_matching).unwrap();
+        session.subscribe("demo/b", on_other).unwrap();
+
+        let push = Push::new(KeyExpr::try_from("demo/a").unwrap(), b"hello");
+        io.borrow_mut()
+            .push_frame(0, &[NetworkMessage::Push(push)])
+            .unwrap();
+        session.read().unwrap();
+
+        assert_eq!(MATCHING.load(Ordering::Relaxed), 1);
+        assert_eq!(OTHER.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn wildcard_subscriptions_are_dispatched() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        fn on_sample(_: &Sample) {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        for (sub, key, matches) in [
+            ("demo/*", "demo/x", true),
+            ("demo/**", "demo/a/b", true),
+            ("a/*/c", "a/b/c", true),
+            ("demo/*", "demo/a/b", false),
+            ("demo/**", "other/a", false),
+            ("a/*/c", "a/b/d", false),
+        ] {
+            let subscribers = [Subscriber {
+                id: 1,
+                keyexpr: KeyExpr::try_from(sub).unwrap(),
+                callback: on_sample,
+            }];
+            let push = Push::new(KeyExpr::try_from(key).unwrap(), b"x");
+
+            let before = CALLS.load(Ordering::Relaxed);
+            dispatch(&subscribers, &push);
+            let called = CALLS.load(Ordering::Relaxed) != before;
+            assert_eq!(called, matches, "{sub} vs {key}");
+        }
+    }
+
+    fn reply(id: u32, keyexpr: &'static str, payload: &'static [u8]) -> NetworkMessage<'static> {
+        NetworkMessage::Response(Response::new(
+            id,
+            KeyExpr::try_from(keyexpr).unwrap(),
+            payload,
+        ))
+    }
+
+    #[test]
+    fn get_returns_the_replies_up_to_the_final_response() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        let id = session.next_id;
+
+        let other = Push::new(KeyExpr::try_from("demo/other").unwrap(), b"x");
+        io.borrow_mut()
+            .push_frame(
+                0,
+                &[reply(id, "demo/a", b"one"), NetworkMessage::Push(other)],
+            )
+            .unwrap();
+        io.borrow_mut()
+            .push_frame(
+                1,
+                &[
+                    reply(id + 1, "demo/b", b"stale"),
+                    reply(id, "demo/b", b"two"),
+                    NetworkMessage::ResponseFinal(ResponseFinal { request_id: id }),
+                ],
+            )
+            .unwrap();
+
+        let replies = session.get("demo/**", "arg=1").unwrap();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(
+            (replies[0].keyexpr.as_str(), &replies[0].payload[..]),
+            ("demo/a", &b"one"[..])
+        );
+        assert_eq!(
+            (replies[1].keyexpr.as_str(), &replies[1].payload[..]),
+            ("demo/b", &b"two"[..])
+        );
+    }
+
+    #[test]
+    fn get_expands_scoped_reply_keys() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        let scope = session.declare_keyexpr("demo").unwrap();
+        let id = session.next_id;
+
+        let scoped =
+            |keyexpr, payload| NetworkMessage::Response(Response::new(id, keyexpr, payload));
+        io.borrow_mut()
+            .push_frame(
+                0,
+                &[
+                    scoped(KeyExpr::with_id(scope, "/a"), b"one"),
+                    scoped(KeyExpr::with_id(scope, ""), b"two"),
+                    NetworkMessage::ResponseFinal(ResponseFinal { request_id: id }),
+                ],
+            )
+            .unwrap();
+        let replies = session.get("demo/**", "").unwrap();
+        let keys: Vec<&str, 2> = replies.iter().map(|r| r.keyexpr.as_str()).collect();
+        assert_eq!(keys, ["demo/a", "demo"]);
+
+        // A scope never declared cannot be expanded
+        let id = session.next_id;
+        io.borrow_mut()
+            .push_frame(
+                1,
+                &[
+                    NetworkMessage::Response(Response::new(id, KeyExpr::with_id(9, "/a"), b"x")),
+                    NetworkMessage::ResponseFinal(ResponseFinal { request_id: id }),
+                ],
+            )
+            .unwrap();
+        assert!(matches!(
+            session.get("demo/**", ""),
+            Err(SessionError::UnknownKeyExprId(9))
+        ));
+    }
+
+    #[test]
+    fn get_gives_up_without_a_final_response() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+
+        for sn in 0..Z_QUERY_MAX_READS as u64 {
+            io.borrow_mut()
+                .push_frame(
+                    sn,
+                    &[NetworkMessage::Push(Push::new(
+                        KeyExpr::try_from("demo/other").unwrap(),
+                        b"x",
+                    ))],
+                )
+                .unwrap();
+        }
+
+        assert!(matches!(
+            session.get("demo/**", ""),
+            Err(SessionError::QueryTimeout)
+        ));
+    }
+
+    #[test]
+    fn keyexpr_mappings_share_a_frame() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        let sent = io.borrow().outbound().len();
+
+        let mappings = [(1, "demo/a"), (2, "demo/b"), (3, "demo/c")];
+        session.declare_keyexprs(&mappings).unwrap();
+
+        let io = io.borrow();
+        assert_eq!(io.outbound().len(), sent + 1);
+        let reader = SliceReader::new(io.outbound().last().unwrap());
+        let msg = TransportMessage::decode(&reader).unwrap();
+        assert!(matches!(msg.body, TransportBody::Frame(_)));
+        for (id, keyexpr) in mappings {
+            let NetworkMessage::Declare(declare) = NetworkMessage::decode(&reader).unwrap() else {
+                panic!("expected a Declare");
+            };
+            assert_eq!(
+                declare.body,
+                DeclareBody::DeclareKeyExpr(DeclareKeyExpr {
+                    id,
+                    keyexpr: KeyExpr::new(keyexpr),
+                })
+            );
+        }
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn received_close_reason_is_kept() {
+        use crate::protocol::transport::close::{reason, Close};
+
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        assert_eq!(session.last_close_reason(), None);
+
+        io.borrow_mut()
+            .push_message(&Close::message(true, reason::EXPIRED))
+            .unwrap();
+        session.read().unwrap();
+
+        assert_eq!(session.last_close_reason(), Some(CloseReason::Expired));
+    }
+
+    #[test]
+    fn non_default_priority_needs_qos() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        let sent = io.borrow().outbound().len();
+
+        let realtime = PutOptions {
+            priority: Priority::RealTime,
+            ..Default::default()
+        };
+        assert!(matches!(
+            session.put_with_options("demo/a", b"x", &realtime),
+            Err(SessionError::TransportError(
+                TransportError::QosNotNegotiated
+            ))
+        ));
+        session.flush().unwrap();
+        assert_eq!(io.borrow().outbound().len(), sent);
+    }
+
+    #[test]
+    fn priority_is_only_sent_on_qos_sessions() {
+        fn last_frame_qos(io: &RefCell<MockIo>) -> Option<u8> {
+            let io = io.borrow();
+            let reader = SliceReader::new(io.outbound().last().unwrap());
+            match TransportMessage::decode(&reader).unwrap().body {
+                TransportBody::Frame(frame) => frame.qos,
+                body => panic!("expected a Frame, got {}", body.kind()),
+            }
+        }
+
+        let io = RefCell::new(MockIo::new());
+        let mut ack = InitSyn::ack(WhatAmI::Router, ZenohID::from(0xbeef), &[0xc0]);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.qos = true;
+        }
+        io.borrow_mut().push_message(&ack).unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        let cfg = Config::builder(ZenohID::from(0x49), WhatAmI::Client)
+            .qos(true)
+            .build();
+        let mut session = open(MockLink::unicast(&io), &cfg).unwrap();
+        let realtime = PutOptions {
+            priority: Priority::RealTime,
+            ..Default::default()
+        };
+        session.put_with_options("demo/a", b"x", &realtime).unwrap();
+        session.flush().unwrap();
+        assert_eq!(last_frame_qos(&io), Some(Priority::RealTime as u8));
+
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        session.put("demo/a", b"x").unwrap();
+        session.flush().unwrap();
+        assert_eq!(last_frame_qos(&io), None);
+    }
+
+    #[test]
+    fn failed_keyexpr_mapping_ends_the_batch() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        let sent = io.borrow().outbound().len();
+
+        assert!(matches!(
+            session.declare_keyexprs(&[(1, "demo/a"), (2, "demo//b")]),
+            Err(SessionError::ProtocolError(
+                ProtocolError::NonCanonicalKeyExpr
+            ))
+        ));
+        // The mapping before the failing one still went out
+        assert_eq!(io.borrow().outbound().len(), sent + 1);
+
+        session.declare_subscriber("demo/c").unwrap();
+        assert_eq!(io.borrow().outbound().len(), sent + 2);
+
+        assert!(matches!(
+            session.declare_keyexprs(&[(0, "demo/a")]),
+            Err(SessionError::ReservedKeyExprId)
+        ));
+        assert_eq!(io.borrow().outbound().len(), sent + 2);
+    }
+
+    #[test]
+    fn reconnect_recovers_from_a_dropped_link() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+
+        io.borrow_mut().fail_recvs(1);
+        assert!(matches!(
+            session.read(),
+            Err(SessionError::TransportError(TransportError::LinkError(
+                link::LinkError::Closed
+            )))
+        ));
+
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xcafe))
+            .unwrap();
+        session
+            .reconnect(&Config::new(ZenohID::from(0x49), WhatAmI::Client))
+            .unwrap();
+        assert_eq!(session.info().zid, ZenohID::from(0x49));
+        assert_eq!(session.info().peer_zid, Some(ZenohID::from(0xcafe)));
+    }
+
+    #[test]
+    fn failed_redeclaration_ends_the_batch() {
+        fn on_sample(_: &Sample) {}
+
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        let long = "demo/a-key-expression-long-enough-to-fill-half-a-small-batch";
+        session.subscribe(long, on_sample).unwrap();
+        session
+            .subscribe(&long[..long.len() - 1], on_sample)
+            .unwrap();
+
+        // The second Declare does not fit a 100 byte batch, sending the
+        // first one fails once the handshake went through
+        io.borrow_mut().clear_outbound();
+        let mut ack = InitSyn::ack(WhatAmI::Router, ZenohID::from(0xbeef), &[0xc0]);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.batch_size = 100;
+        }
+        io.borrow_mut().push_message(&ack).unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        io.borrow_mut().fail_sends_after(2, 1);
+        assert!(matches!(
+            session.reconnect(&Config::new(ZenohID::from(0x49), WhatAmI::Client)),
+            Err(SessionError::TransportError(TransportError::LinkError(
+                link::LinkError::IoError
+            )))
+        ));
+        let sent = io.borrow().outbound().len();
+
+        session.declare_subscriber("demo/c").unwrap();
+        assert_eq!(io.borrow().outbound().len(), sent + 1);
+    }
+
+    #[test]
+    fn reconnect_declares_again_under_the_original_ids() {
+        fn on_sample(_: &Sample) {}
+
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        session.subscribe("demo/a", on_sample).unwrap();
+        session.declare_subscriber("demo/unkept").unwrap();
+        session.subscribe("demo/b", on_sample).unwrap();
+        session.declare_keyexpr("demo/k").unwrap();
+        session.declare_keyexprs(&[(5, "demo/five")]).unwrap();
+
+        io.borrow_mut().clear_outbound();
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        session
+            .reconnect(&Config::new(ZenohID::from(0x49), WhatAmI::Client))
+            .unwrap();
+
+        let io = io.borrow();
+        let reader = SliceReader::new(io.outbound().last().unwrap());
+        let msg = TransportMessage::decode(&reader).unwrap();
+        assert!(matches!(msg.body, TransportBody::Frame(_)));
+        let mut declared: Vec<(u32, &str), 8> = Vec::new();
+        while reader.remaining() > 0 {
+            let NetworkMessage::Declare(declare) = NetworkMessage::decode(&reader).unwrap() else {
+                panic!("expected a Declare");
+            };
+            let entry = match declare.body {
+                DeclareBody::DeclareSubscriber(d) => (d.id, d.keyexpr.suffix),
+                DeclareBody::DeclareKeyExpr(d) => (d.id as u32, d.keyexpr.suffix),
+            };
+            declared.push(entry).unwrap();
+        }
+        assert_eq!(
+            declared,
+            [
+                (1, "demo/a"),
+                (3, "demo/b"),
+                (1, "demo/k"),
+                (5, "demo/five")
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_is_flushed_once_its_window_elapsed() {
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let cfg = Config::builder(ZenohID::from(0x49), WhatAmI::Client)
+            .batch_window_ms(10)
+            .build();
+        let mut session = open(MockLink::unicast(&io), &cfg).unwrap();
+        let sent = io.borrow().outbound().len();
+
+        session.put("demo/a", b"x").unwrap();
+        session.tick(9).unwrap();
+        assert_eq!(io.borrow().outbound().len(), sent);
+
+        session.tick(1).unwrap();
+        assert_eq!(io.borrow().outbound().len(), sent + 1);
+        let io = io.borrow();
+        let reader = SliceReader::new(io.outbound().last().unwrap());
+        let msg = TransportMessage::decode(&reader).unwrap();
+        assert!(matches!(msg.body, TransportBody::Frame(_)));
+    }
+
+    #[test]
+    fn close_sends_the_reason_to_the_peer() {
+        use crate::protocol::transport::close::{reason, Close};
+
+        let io = RefCell::new(MockIo::new());
+        let session = open_mock(&io);
+        session.close(CloseReason::MaxLinks).unwrap();
+
+        let io = io.borrow();
+        let reader = SliceReader::new(io.outbound().last().unwrap());
+        assert_eq!(
+            TransportMessage::decode(&reader).unwrap(),
+            Close::message(true, reason::MAX_LINKS)
+        );
+    }
+
+    #[test]
+    fn shutdown_flushes_before_closing() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        session.put("demo/a", b"x").unwrap();
+        let sent = io.borrow().outbound().len();
+
+        session.shutdown(10).unwrap();
+
+        let io = io.borrow();
+        let kinds: Vec<&str, 2> = io.outbound()[sent..]
+            .iter()
+            .map(|frame| {
+                let reader = SliceReader::new(frame);
+                TransportMessage::decode(&reader).unwrap().body.kind()
+            })
+            .collect();
+        assert_eq!(kinds, ["Frame", "Close"]);
+    }
+
+    #[test]
+    fn blocking_put_does_not_resend_a_cut_frame() {
+        let io = RefCell::new(MockIo::new());
+        let mut session = open_mock(&io);
+        let sent = io.borrow().outbound().len();
+
+        // Two such puts do not fit a batch, the second one sends the first
+        let payload = [0x5a; 1500];
+        let block = PutOptions {
+            congestion_control: CongestionControl::Block,
+            ..Default::default()
+        };
+        session
+            .put_with_options("demo/a", &payload, &block)
+            .unwrap();
+        io.borrow_mut().cut_sends(1);
+        assert!(matches!(
+            session.put_with_options("demo/a", &payload, &block),
+            Err(SessionError::TransportError(TransportError::LinkError(
+                link::LinkError::PartialWrite { .. }
+            )))
+        ));
+        assert_eq!(io.borrow().outbound().len(), sent + 1);
+
+        // Nor does a later flush
+        session.flush().unwrap();
+        assert_eq!(io.borrow().outbound().len(), sent + 1);
+    }
+
+    #[test]
+    fn blocking_put_retries_a_failed_write() {
+        use core::sync::atomic::{AtomicU32, Ordering};
+
+        static DELAYED_MS: AtomicU32 = AtomicU32::new(0);
+
+        fn delay_ms(ms: u32) {
+            DELAYED_MS.fetch_add(ms, Ordering::Relaxed);
+        }
+
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let cfg = Config::builder(ZenohID::from(0x49), WhatAmI::Client)
+            .delay_ms(delay_ms)
+            .build();
+        let mut session = open(MockLink::unicast(&io), &cfg).unwrap();
+        let sent = io.borrow().outbound().len();
+
+        // Two such puts do not fit a batch, the second one sends the first
+        let payload = [0x5a; 1500];
+        let block = PutOptions {
+            congestion_control: CongestionControl::Block,
+            ..Default::default()
+        };
+        session
+            .put_with_options("demo/a", &payload, &block)
+            .unwrap();
+        io.borrow_mut().fail_sends(1);
+        session
+            .put_with_options("demo/a", &payload, &block)
+            .unwrap();
+
+        assert_eq!(io.borrow().outbound().len(), sent + 1);
+        assert_eq!(DELAYED_MS.load(Ordering::Relaxed), Z_CONGESTION_BACKOFF_MS);
+
+        // Dropping gives up on the first failure
+        io.borrow_mut().fail_sends(1);
+        assert!(matches!(
+            session.put_with_options("demo/a", &payload, &PutOptions::default()),
+            Err(SessionError::TransportError(TransportError::LinkError(
+                link::LinkError::IoError
+            )))
+        ));
+    }
+}
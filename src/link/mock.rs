@@ -0,0 +1,376 @@
+//! Scripted in-memory link for host tests.
+//!
+//! A [`MockIo`] holds the frames the link will hand out on `recv`, in order,
+//! and records every frame sent through it. [`MockLink`] only borrows it so the
+//! test can still inspect the traffic once the link has been moved into a
+//! session.
+//!
+//! [`MockSerial`] plays the UART under a `SerialIntf`, looping back whatever
+//! was written so the serial framing can be checked against its own decoder.
+//!
+//! With `std`, [`FakeRouter`] answers the unicast handshake over a TCP
+//! loopback so a session can be opened end to end on the host.
+
+use core::cell::RefCell;
+
+use heapless::{Deque, Vec};
+
+use crate::{
+    iobuf::ZVec,
+    protocol::{
+        network::NetworkMessage,
+        transport::{frame::Frame, init::InitSyn, open::OpenSyn, TransportMessage},
+        whatami::WhatAmI,
+        ZenohID,
+    },
+    transport::TransportError,
+    Z_MAX_MTU, Z_TRANSPORT_LEASE,
+};
+
+use super::{Endpoint, Link, LinkCapabilities, LinkError, LinkIntf, TransportCap, TransportFlow};
+
+pub const Z_MOCK_MAX_FRAMES: usize = 8;
+pub const Z_MOCK_SERIAL_SIZE: usize = 4096;
+
+pub type MockFrame = Vec<u8, Z_MAX_MTU>;
+
+#[derive(Default)]
+pub struct MockIo {
+    inbound: Deque<MockFrame, Z_MOCK_MAX_FRAMES>,
+    outbound: Vec<MockFrame, Z_MOCK_MAX_FRAMES>,
+    failing_sends: usize,
+    passing_sends: usize,
+    cut_sends: usize,
+    failing_recvs: usize,
+}
+
+impl MockIo {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Queues `frame` to be returned by a later `recv`.
+    pub fn push_inbound(&mut self, frame: &[u8]) -> Result<(), LinkError> {
+        let frame = Vec::from_slice(frame).map_err(|_| LinkError::InvalidParameter)?;
+        self.inbound
+            .push_back(frame)
+            .map_err(|_| LinkError::InvalidParameter)
+    }
+
+    pub fn push_message(&mut self, msg: &TransportMessage) -> Result<(), TransportError> {
+        let mut buf = ZVec::new();
+        msg.encode(&mut buf)?;
+        self.push_inbound(buf.as_slice())?;
+        Ok(())
+    }
+
+    /// Queues a reliable Frame with sequence number `sn` carrying `msgs`.
+    pub fn push_frame(&mut self, sn: u64, msgs: &[NetworkMessage]) -> Result<(), TransportError> {
+        let mut buf = ZVec::new();
+        Frame::message(true, sn).encode(&mut buf)?;
+        for msg in msgs {
+            msg.encode(&mut buf)?;
+        }
+        self.push_inbound(buf.as_slice())?;
+        Ok(())
+    }
+
+    pub fn push_init_ack(&mut self, zid: ZenohID, cookie: &[u8]) -> Result<(), TransportError> {
+        self.push_message(&InitSyn::ack(WhatAmI::Router, zid, cookie))
+    }
+
+    pub fn push_open_ack(&mut self, initial_sn: u64) -> Result<(), TransportError> {
+        self.push_message(&OpenSyn::ack(Z_TRANSPORT_LEASE, initial_sn))
+    }
+
+    /// Queues the InitAck and OpenAck answering a unicast handshake.
+    pub fn push_handshake(&mut self, zid: ZenohID) -> Result<(), TransportError> {
+        self.push_init_ack(zid, &[0xc0, 0x0c, 0x1e])?;
+        self.push_open_ack(0)
+    }
+
+    /// Makes the next `count` sends fail with [`LinkError::IoError`].
+    pub fn fail_sends(&mut self, count: usize) {
+        self.fail_sends_after(0, count);
+    }
+
+    /// Lets the next `sent` sends through, then fails `count` of them with
+    /// [`LinkError::IoError`].
+    pub fn fail_sends_after(&mut self, sent: usize, count: usize) {
+        self.passing_sends = sent;
+        self.failing_sends = count;
+    }
+
+    /// Makes the next `count` sends stop halfway: the first half of the
+    /// frame is recorded and [`LinkError::PartialWrite`] returned.
+    pub fn cut_sends(&mut self, count: usize) {
+        self.cut_sends = count;
+    }
+
+    /// Makes the next `count` receives fail with [`LinkError::Closed`], as
+    /// when the peer dropped the link.
+    pub fn fail_recvs(&mut self, count: usize) {
+        self.failing_recvs = count;
+    }
+
+    /// Frames sent through the link so far, oldest first.
+    pub fn outbound(&self) -> &[MockFrame] {
+        &self.outbound
+    }
+
+    pub fn clear_outbound(&mut self) {
+        self.outbound.clear();
+    }
+
+    pub fn pending_inbound(&self) -> usize {
+        self.inbound.len()
+    }
+}
+
+pub struct MockLink<'a> {
+    io: &'a RefCell<MockIo>,
+    cap: TransportCap,
+    flow: TransportFlow,
+    mtu: usize,
+}
+
+impl<'a> MockLink<'a> {
+    pub fn unicast(io: &'a RefCell<MockIo>) -> Self {
+        MockLink {
+            io,
+            cap: TransportCap::Unicast,
+            flow: TransportFlow::DATAGRAM,
+            mtu: Z_MAX_MTU,
+        }
+    }
+
+    pub fn multicast(io: &'a RefCell<MockIo>) -> Self {
+        MockLink {
+            io,
+            cap: TransportCap::Multicast,
+            flow: TransportFlow::DATAGRAM,
+            mtu: Z_MAX_MTU,
+        }
+    }
+
+    /// A unicast stream link, `recv` may then hand out a queued frame over
+    /// several calls.
+    pub fn stream(io: &'a RefCell<MockIo>) -> Self {
+        MockLink {
+            io,
+            cap: TransportCap::Unicast,
+            flow: TransportFlow::STREAM,
+            mtu: Z_MAX_MTU,
+        }
+    }
+
+    /// Advertises `mtu` instead of [`Z_MAX_MTU`].
+    pub fn with_mtu(mut self, mtu: usize) -> Self {
+        self.mtu = mtu;
+        self
+    }
+}
+
+impl<'a> Endpoint for MockLink<'a> {
+    type L = MockLink<'a>;
+
+    fn create_link_from_endpoint(ep: Self) -> Link<Self::L> {
+        let cap = LinkCapabilities::new(ep.cap, ep.flow, true);
+        Link {
+            mtu: ep.mtu,
+            intf: ep,
+            cap,
+        }
+    }
+}
+
+impl LinkIntf for MockLink<'_> {
+    fn open(&mut self) -> Result<(), LinkError> {
+        Ok(())
+    }
+
+    fn send(&mut self, msg: &[u8]) -> Result<(), LinkError> {
+        let mut io = self.io.borrow_mut();
+        if io.passing_sends > 0 {
+            io.passing_sends -= 1;
+        } else if io.failing_sends > 0 {
+            io.failing_sends -= 1;
+            return Err(LinkError::IoError);
+        }
+
+        if io.cut_sends > 0 {
+            io.cut_sends -= 1;
+            let written = msg.len() / 2;
+            let frame =
+                Vec::from_slice(&msg[..written]).map_err(|_| LinkError::InvalidParameter)?;
+            io.outbound.push(frame).map_err(|_| LinkError::IoError)?;
+            return Err(LinkError::PartialWrite { written });
+        }
+
+        let frame = Vec::from_slice(msg).map_err(|_| LinkError::InvalidParameter)?;
+        io.outbound.push(frame).map_err(|_| LinkError::IoError)
+    }
+
+    /// Hands out the next scripted frame, [`LinkError::Timeout`] once none are left.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, LinkError> {
+        let mut io = self.io.borrow_mut();
+        if io.failing_recvs > 0 {
+            io.failing_recvs -= 1;
+            return Err(LinkError::Closed);
+        }
+
+        let mut frame = io.inbound.pop_front().ok_or(LinkError::Timeout)?;
+        if frame.len() > buf.len() && self.flow == TransportFlow::STREAM {
+            let rest = Vec::from_slice(&frame[buf.len()..]).map_err(|_| LinkError::IoError)?;
+            let _ = io.inbound.push_front(rest);
+            frame.truncate(buf.len());
+        }
+        if frame.len() > buf.len() {
+            return Err(LinkError::InvalidParameter);
+        }
+        buf[..frame.len()].copy_from_slice(&frame);
+        Ok(frame.len())
+    }
+}
+
+pub type MockWire = Deque<u8, Z_MOCK_SERIAL_SIZE>;
+
+/// One end of a byte pipe, usable as both the RX and TX of a `SerialIntf`.
+///
+/// Reading an empty wire returns 0 bytes, which the serial link reports as closed.
+#[derive(Clone, Copy)]
+pub struct MockSerial<'a> {
+    wire: &'a RefCell<MockWire>,
+}
+
+impl<'a> MockSerial<'a> {
+    pub fn new(wire: &'a RefCell<MockWire>) -> Self {
+        MockSerial { wire }
+    }
+}
+
+impl embedded_io::ErrorType for MockSerial<'_> {
+    type Error = core::convert::Infallible;
+}
+
+impl embedded_io::Read for MockSerial<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut wire = self.wire.borrow_mut();
+        let mut n = 0;
+        while n < buf.len() {
+            match wire.pop_front() {
+                Some(b) => buf[n] = b,
+                None => break,
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl embedded_io::Write for MockSerial<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let mut wire = self.wire.borrow_mut();
+        let mut n = 0;
+        for b in buf {
+            if wire.push_back(*b).is_err() {
+                break;
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Delay that returns immediately.
+pub struct NoDelay;
+
+impl embedded_hal::delay::DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// A router reduced to the unicast handshake, listening on a loopback port.
+/// It answers the InitSyn of one client with an InitAck and its OpenSyn with
+/// an OpenAck, then waits for the client to hang up.
+#[cfg(feature = "std")]
+pub struct FakeRouter {
+    listener: std::net::TcpListener,
+    zid: ZenohID,
+    batch_size: u16,
+}
+
+#[cfg(feature = "std")]
+impl FakeRouter {
+    const COOKIE: &'static [u8] = &[0xc0, 0x0c, 0x1e];
+
+    pub fn bind(zid: ZenohID) -> Result<Self, LinkError> {
+        let listener =
+            std::net::TcpListener::bind("127.0.0.1:0").map_err(|_| LinkError::IoError)?;
+        Ok(FakeRouter {
+            listener,
+            zid,
+            batch_size: crate::Z_BATCH_UNICAST_SIZE,
+        })
+    }
+
+    /// Offers `batch_size` in the InitAck instead of `Z_BATCH_UNICAST_SIZE`.
+    pub fn with_batch_size(mut self, batch_size: u16) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr, LinkError> {
+        self.listener.local_addr().map_err(|_| LinkError::IoError)
+    }
+
+    /// Serves a single client on its own thread, the handle yields the ZID
+    /// the client announced.
+    pub fn spawn(self) -> std::thread::JoinHandle<Result<ZenohID, TransportError>> {
+        std::thread::spawn(move || self.serve())
+    }
+
+    fn serve(self) -> Result<ZenohID, TransportError> {
+        use crate::{iobuf::SliceReader, protocol::transport::TransportBody};
+
+        let (stream, _) = self.listener.accept().map_err(|_| LinkError::IoError)?;
+        let mut link = super::open(super::tcp::TcpIntf::new(stream))?;
+        let mut buf = [0u8; Z_MAX_MTU];
+
+        let len = link.recv_msg(&mut buf)?;
+        let client = match TransportMessage::decode(&SliceReader::new(&buf[..len]))?.body {
+            TransportBody::InitSyn(ism) => ism.zid,
+            _ => return Err(TransportError::UnexpectMsg),
+        };
+        let mut ack = InitSyn::ack(WhatAmI::Router, self.zid, Self::COOKIE);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.batch_size = self.batch_size;
+        }
+        Self::send(&mut link, &ack)?;
+
+        let len = link.recv_msg(&mut buf)?;
+        match TransportMessage::decode(&SliceReader::new(&buf[..len]))?.body {
+            TransportBody::OpenSyn(osm) if osm.cookie == Some(Self::COOKIE) => {}
+            TransportBody::OpenSyn(_) => return Err(TransportError::CookieMismatch),
+            _ => return Err(TransportError::UnexpectMsg),
+        }
+        Self::send(&mut link, &OpenSyn::ack(Z_TRANSPORT_LEASE, 0))?;
+
+        // Anything sent once the transport is open is dropped
+        while link.recv_msg(&mut buf).is_ok() {}
+
+        Ok(client)
+    }
+
+    fn send(
+        link: &mut Link<super::tcp::TcpIntf>,
+        msg: &TransportMessage,
+    ) -> Result<(), TransportError> {
+        let mut buf = ZVec::new();
+        msg.encode(&mut buf)?;
+        link.send_msg(buf.as_slice())
+    }
+}
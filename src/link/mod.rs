@@ -4,9 +4,15 @@ use thiserror::Error;
 
 use crate::transport::TransportError;
 
+#[cfg(feature = "test-utils")]
+pub mod mock;
+pub mod raweth;
 pub mod serial;
+#[cfg(feature = "std")]
+pub mod tcp;
 
 #[derive(Debug, Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum LinkError {
     #[error("Invalid Frame")]
     InvalidFrame(#[from] DestBufTooSmallError),
@@ -18,6 +24,39 @@ pub enum LinkError {
     InvalidParameter,
     #[error("Io Error")]
     IoError,
+    /// The serial port failed with an error it did not retry itself.
+    #[error("Io Error: {0:?}")]
+    Io(embedded_io::ErrorKind),
+    #[error("Timeout")]
+    Timeout,
+    #[error("Frame longer than the receive buffer")]
+    FrameTooLong,
+    /// The length field of a serial frame does not match its decoded size.
+    #[error("Malformed frame")]
+    MalformedFrame,
+    #[error("Link closed by peer")]
+    Closed,
+    #[error("Connect failed")]
+    ConnectFailed,
+    /// Only the first `written` bytes of the frame left the link.
+    #[error("Partial write of {written} bytes")]
+    PartialWrite { written: usize },
+}
+
+impl LinkError {
+    /// Whether the link may take the same data once it had some time to
+    /// drain, e.g. a full buffer or an interrupted write. A write cut short
+    /// is not: resending the whole frame after its start already left
+    /// would corrupt the framing.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            LinkError::IoError
+                | LinkError::Io(
+                    embedded_io::ErrorKind::Interrupted | embedded_io::ErrorKind::TimedOut
+                )
+        )
+    }
 }
 
 pub trait LinkIntf: Sized {
@@ -26,8 +65,31 @@ pub trait LinkIntf: Sized {
     fn send(&mut self, msg: &[u8]) -> Result<(), LinkError>;
 
     fn recv(&mut self, buf: &mut [u8]) -> Result<usize, LinkError>;
+
+    /// Waits at most `timeout_ms` for pending outbound bytes to leave the link.
+    fn drain(&mut self, _timeout_ms: u32) -> Result<(), LinkError> {
+        Ok(())
+    }
 }
 
+/// Hooks for link events, e.g. to trace a handshake without `defmt`. Every
+/// method defaults to doing nothing, `()` observes nothing at all.
+pub trait LinkObserver {
+    /// An INIT went out to (re)start the link.
+    fn on_init_sent(&mut self) {}
+
+    /// The peer answered an INIT with a RESET.
+    fn on_reset(&mut self) {}
+
+    /// The peer acknowledged the INIT, the link is up.
+    fn on_connect(&mut self) {}
+
+    /// A frame with `header` flags and `len` bytes on the wire was sent.
+    fn on_frame_sent(&mut self, _header: u8, _len: usize) {}
+}
+
+impl LinkObserver for () {}
+
 pub trait Endpoint: Sized {
     type L: LinkIntf;
 
@@ -40,28 +102,32 @@ pub struct Link<Intf> {
     pub cap: LinkCapabilities,
 }
 
-impl<RX, TX, Delay> Endpoint for serial::SerialIntf<RX, TX, Delay>
+impl<RX, TX, Delay, const N: usize, O> Endpoint for serial::SerialIntf<RX, TX, Delay, N, O>
 where
     RX: embedded_io::Read,
     TX: embedded_io::Write,
     Delay: DelayNs,
+    O: LinkObserver,
 {
-    type L = serial::SerialIntf<RX, TX, Delay>;
+    type L = serial::SerialIntf<RX, TX, Delay, N, O>;
 
     fn create_link_from_endpoint(ep: Self) -> Link<Self::L> {
+        // Frames are only sent without a checksum over a reliable link
+        let reliable = ep.crc() == serial::CrcKind::None;
         Link {
             intf: ep,
-            mtu: 1500,
-            cap: LinkCapabilities::new(TransportCap::Unicast, TransportFlow::DATAGRAM, false),
+            mtu: Self::MTU,
+            cap: LinkCapabilities::new(TransportCap::Unicast, TransportFlow::DATAGRAM, reliable),
         }
     }
 }
 
-impl<RX, TX, Delay> LinkIntf for serial::SerialIntf<RX, TX, Delay>
+impl<RX, TX, Delay, const N: usize, O> LinkIntf for serial::SerialIntf<RX, TX, Delay, N, O>
 where
     RX: embedded_io::Read,
     TX: embedded_io::Write,
     Delay: DelayNs,
+    O: LinkObserver,
 {
     fn open(&mut self) -> Result<(), LinkError> {
         self.connect()?;
@@ -75,6 +141,10 @@ where
     fn recv(&mut self, buf: &mut [u8]) -> Result<usize, LinkError> {
         self.recv(buf)
     }
+
+    fn drain(&mut self, timeout_ms: u32) -> Result<(), LinkError> {
+        self.drain(timeout_ms)
+    }
 }
 
 impl<I> Link<I>
@@ -85,11 +155,15 @@ where
         self.intf.open()
     }
 
+    /// Sends `msg` as one transport message. Stream links do not keep message
+    /// boundaries, so there it is prefixed with its length as a little-endian u16.
     pub fn send_msg(&mut self, msg: &[u8]) -> Result<(), TransportError> {
         match self.cap.flow() {
             TransportFlow::DATAGRAM => {}
             TransportFlow::STREAM => {
-                unimplemented!()
+                let len = u16::try_from(msg.len())
+                    .map_err(|_| TransportError::MessageTooLong(msg.len()))?;
+                self.intf.send(&len.to_le_bytes())?;
             }
         }
 
@@ -98,19 +172,42 @@ where
         Ok(())
     }
 
+    /// Receives one transport message into `data`, returning its length.
     pub fn recv_msg(&mut self, data: &mut [u8]) -> Result<usize, TransportError> {
         let msg = match self.cap.flow() {
             TransportFlow::STREAM => {
-                unimplemented!()
-            }
-            TransportFlow::DATAGRAM => {
-                let size = self.intf.recv(data)?;
-                size
+                let mut len = [0u8; 2];
+                self.recv_exact(&mut len)?;
+                let len = u16::from_le_bytes(len) as usize;
+                if len > data.len() {
+                    return Err(LinkError::FrameTooLong.into());
+                }
+                self.recv_exact(&mut data[..len])?;
+                len
             }
+            TransportFlow::DATAGRAM => self.intf.recv(data)?,
         };
 
         Ok(msg)
     }
+
+    /// Reads from a stream link until `buf` is full.
+    fn recv_exact(&mut self, buf: &mut [u8]) -> Result<(), LinkError> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.intf.recv(&mut buf[read..])? {
+                0 => return Err(LinkError::Closed),
+                n => read += n,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn drain(&mut self, timeout_ms: u32) -> Result<(), TransportError> {
+        self.intf.drain(timeout_ms)?;
+        Ok(())
+    }
 }
 
 #[repr(u8)]
@@ -149,7 +246,7 @@ impl From<u8> for TransportFlow {
     }
 }
 
-#[derive(Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct LinkCapabilities(u8);
 
 impl LinkCapabilities {
@@ -161,13 +258,29 @@ impl LinkCapabilities {
         lc
     }
 
+    /// The packed capabilities: transport in bits 7-6, flow in bit 5 and
+    /// reliability in bit 4.
+    pub fn to_u8(&self) -> u8 {
+        self.0
+    }
+
+    /// Reads capabilities packed by [`LinkCapabilities::to_u8`], the low
+    /// nibble is ignored and the reserved transport value is rejected.
+    pub fn from_u8(b: u8) -> Result<Self, LinkError> {
+        if b >> 6 == 0b11 {
+            return Err(LinkError::InvalidParameter);
+        }
+
+        Ok(LinkCapabilities(b & 0xf0))
+    }
+
     pub fn transport(&self) -> TransportCap {
         let b = (self.0 >> 6) & 0b11;
         TransportCap::from(b)
     }
 
     pub fn set_transport(&mut self, t: TransportCap) {
-        self.0 = self.0 | ((t as u8) << 6);
+        self.0 = (self.0 & !(0b11 << 6)) | ((t as u8) << 6);
     }
 
     pub fn flow(&self) -> TransportFlow {
@@ -176,7 +289,7 @@ impl LinkCapabilities {
     }
 
     pub fn set_flow(&mut self, f: TransportFlow) {
-        self.0 = self.0 | ((f as u8) << 5);
+        self.0 = (self.0 & !(0b1 << 5)) | ((f as u8) << 5);
     }
 
     pub fn reliable(&self) -> bool {
@@ -184,11 +297,7 @@ impl LinkCapabilities {
     }
 
     pub fn set_reliable(&mut self, r: bool) {
-        if r {
-            self.0 = self.0 | (1 << 4);
-        } else {
-            self.0 = self.0 & !(1 << 4);
-        }
+        self.0 = (self.0 & !(0b1 << 4)) | ((r as u8) << 4);
     }
 }
 
@@ -199,3 +308,134 @@ pub fn open<L: LinkIntf, E: Endpoint<L = L>>(ep: E) -> Result<Link<L>, LinkError
 
     Ok(l)
 }
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::mock::{MockIo, MockLink};
+    use super::*;
+    use crate::iobuf::{Reader, ZVec};
+
+    const MSG: &[u8] = &[0x01, 0x02, 0x03];
+
+    #[test]
+    fn capabilities_round_trip_through_a_byte() {
+        for transport in [
+            TransportCap::Unicast,
+            TransportCap::Multicast,
+            TransportCap::Raweth,
+        ] {
+            for flow in [TransportFlow::DATAGRAM, TransportFlow::STREAM] {
+                for reliable in [false, true] {
+                    let cap = LinkCapabilities::new(transport, flow, reliable);
+                    let decoded = LinkCapabilities::from_u8(cap.to_u8()).unwrap();
+                    assert_eq!(decoded, cap);
+                    assert_eq!(decoded.transport(), transport);
+                    assert_eq!(decoded.flow(), flow);
+                    assert_eq!(decoded.reliable(), reliable);
+                }
+            }
+        }
+
+        assert!(matches!(
+            LinkCapabilities::from_u8(0xc0),
+            Err(LinkError::InvalidParameter)
+        ));
+    }
+
+    #[test]
+    fn setters_replace_the_previous_value() {
+        let mut cap = LinkCapabilities::new(TransportCap::Unicast, TransportFlow::DATAGRAM, true);
+
+        cap.set_transport(TransportCap::Multicast);
+        cap.set_transport(TransportCap::Unicast);
+        assert_eq!(cap.transport(), TransportCap::Unicast);
+
+        cap.set_flow(TransportFlow::STREAM);
+        cap.set_flow(TransportFlow::DATAGRAM);
+        assert_eq!(cap.flow(), TransportFlow::DATAGRAM);
+
+        cap.set_reliable(false);
+        assert!(!cap.reliable());
+        assert_eq!(
+            cap,
+            LinkCapabilities::new(TransportCap::Unicast, TransportFlow::DATAGRAM, false)
+        );
+    }
+
+    #[test]
+    fn serial_link_is_an_unreliable_unicast_datagram() {
+        use super::mock::{MockSerial, MockWire, NoDelay};
+
+        let wire = RefCell::new(MockWire::new());
+        let port = MockSerial::new(&wire);
+        let link = serial::SerialIntf::create_link_from_endpoint(serial::SerialIntf::new(
+            port, port, NoDelay,
+        ));
+        assert_eq!(link.cap.transport(), TransportCap::Unicast);
+        assert_eq!(link.cap.flow(), TransportFlow::DATAGRAM);
+        assert!(!link.cap.reliable());
+    }
+
+    #[test]
+    fn datagram_messages_are_not_prefixed() {
+        let io = RefCell::new(MockIo::new());
+        let mut link = open(MockLink::unicast(&io)).unwrap();
+
+        link.send_msg(MSG).unwrap();
+        assert_eq!(io.borrow().outbound()[0].as_slice(), MSG);
+
+        io.borrow_mut().push_inbound(MSG).unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(link.recv_msg(&mut buf).unwrap(), MSG.len());
+        assert_eq!(&buf[..MSG.len()], MSG);
+    }
+
+    #[test]
+    fn stream_messages_carry_a_length_prefix() {
+        let io = RefCell::new(MockIo::new());
+        let mut link = open(MockLink::stream(&io)).unwrap();
+
+        link.send_msg(MSG).unwrap();
+        let sent: std::vec::Vec<u8> = io.borrow().outbound().iter().flatten().copied().collect();
+        assert_eq!(sent, [&[0x03, 0x00], MSG].concat());
+
+        io.borrow_mut().push_inbound(&sent).unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(link.recv_msg(&mut buf).unwrap(), MSG.len());
+        assert_eq!(&buf[..MSG.len()], MSG);
+        assert_eq!(io.borrow().pending_inbound(), 0);
+    }
+
+    #[test]
+    fn stream_messages_are_bounded_by_u16() {
+        let io = RefCell::new(MockIo::new());
+        let mut link = open(MockLink::stream(&io)).unwrap();
+
+        let msg = [0u8; u16::MAX as usize + 1];
+        assert!(matches!(
+            link.send_msg(&msg),
+            Err(TransportError::MessageTooLong(65536))
+        ));
+    }
+
+    #[test]
+    fn short_message_truncates_the_receive_slice() {
+        let io = RefCell::new(MockIo::new());
+        let mut link = open(MockLink::unicast(&io)).unwrap();
+        io.borrow_mut().push_inbound(MSG).unwrap();
+
+        let mut cache = ZVec::new();
+        let mut s = cache.extract_slice(16).unwrap();
+        let size = link.recv_msg(s.as_mut()).unwrap();
+        assert!(s.truncate(17).is_err());
+        s.truncate(size).unwrap();
+
+        assert_eq!(s.remaining(), MSG.len());
+        let mut read = [0u8; 3];
+        s.read_exact(&mut read).unwrap();
+        assert_eq!(read, MSG);
+        assert!(s.read_u8().is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Raw Ethernet link.
+//!
+//! The link does not drive a MAC itself, it hands every batch to a
+//! user-supplied `send_frame` and pulls incoming ones from `recv_frame`, which
+//! are responsible for the Ethernet header and the zenoh ethertype. The
+//! transport is opened with the unicast handshake, the callbacks address the
+//! frames to the peer.
+
+use super::{Endpoint, Link, LinkCapabilities, LinkError, LinkIntf, TransportCap, TransportFlow};
+
+/// Largest Ethernet payload, the frame header is added by `send_frame`.
+pub const RAWETH_MTU: usize = 1500;
+
+pub struct RawEthIntf<S, R> {
+    send_frame: S,
+    recv_frame: R,
+}
+
+impl<S, R> RawEthIntf<S, R>
+where
+    S: FnMut(&[u8]) -> Result<(), LinkError>,
+    R: FnMut(&mut [u8]) -> Result<usize, LinkError>,
+{
+    pub fn new(send_frame: S, recv_frame: R) -> Self {
+        RawEthIntf {
+            send_frame,
+            recv_frame,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        "RawEth"
+    }
+}
+
+impl<S, R> Endpoint for RawEthIntf<S, R>
+where
+    S: FnMut(&[u8]) -> Result<(), LinkError>,
+    R: FnMut(&mut [u8]) -> Result<usize, LinkError>,
+{
+    type L = RawEthIntf<S, R>;
+
+    fn create_link_from_endpoint(ep: Self) -> Link<Self::L> {
+        Link {
+            intf: ep,
+            mtu: RAWETH_MTU,
+            cap: LinkCapabilities::new(TransportCap::Raweth, TransportFlow::DATAGRAM, false),
+        }
+    }
+}
+
+impl<S, R> LinkIntf for RawEthIntf<S, R>
+where
+    S: FnMut(&[u8]) -> Result<(), LinkError>,
+    R: FnMut(&mut [u8]) -> Result<usize, LinkError>,
+{
+    fn open(&mut self) -> Result<(), LinkError> {
+        Ok(())
+    }
+
+    fn send(&mut self, msg: &[u8]) -> Result<(), LinkError> {
+        (self.send_frame)(msg)
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, LinkError> {
+        (self.recv_frame)(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::iobuf::{SliceReader, ZVec};
+    use crate::protocol::{
+        transport::{init::InitSyn, open::OpenSyn, TransportMessage},
+        whatami::WhatAmI,
+        ZenohID,
+    };
+    use crate::transport::Transport;
+    use crate::Config;
+
+    #[test]
+    fn handshake_completes_over_the_frame_callbacks() {
+        let mut replies = VecDeque::new();
+        for msg in [
+            InitSyn::ack(WhatAmI::Router, ZenohID::from(0xbeef), &[0xc0, 0xff]),
+            OpenSyn::ack(10000, 0),
+        ] {
+            let mut buf = ZVec::new();
+            msg.encode(&mut buf).unwrap();
+            replies.push_back(buf.as_slice().to_vec());
+        }
+        let replies = RefCell::new(replies);
+        let sent = RefCell::new(std::vec::Vec::new());
+
+        let intf = RawEthIntf::new(
+            |frame: &[u8]| {
+                let reader = SliceReader::new(frame);
+                let msg = TransportMessage::decode(&reader).map_err(|_| LinkError::IoError)?;
+                sent.borrow_mut().push(msg.body.kind());
+                Ok(())
+            },
+            |buf: &mut [u8]| {
+                let frame = replies.borrow_mut().pop_front().ok_or(LinkError::Timeout)?;
+                buf[..frame.len()].copy_from_slice(&frame);
+                Ok(frame.len())
+            },
+        );
+
+        let cfg = Config::new(ZenohID::from(0x49), WhatAmI::Client);
+        let transport = Transport::new(intf, &cfg).unwrap();
+        assert!(matches!(transport, Transport::Unicast(_)));
+        assert_eq!(
+            transport.link_capabilities().transport(),
+            TransportCap::Raweth
+        );
+        assert_eq!(
+            transport.peer(),
+            Some((ZenohID::from(0xbeef), WhatAmI::Router))
+        );
+        assert_eq!(*sent.borrow(), ["InitSyn", "OpenSyn"]);
+        assert!(replies.borrow().is_empty());
+    }
+}
@@ -1,63 +1,111 @@
+//! ZSerial Frame Format
+//!
+//! Using COBS
+//!
+//! +-+-+----+------------+--------+-+
+//! |O|H|XXXX|ZZZZ....ZZZZ|CCCCCCCC|0|
+//! +-+----+------------+--------+-+
+//! |O| |Len |   Data     |  CRC32 |C|
+//! +-+-+-2--+----N-------+---4----+-+
+//!
+//! Header: 1byte
+//! +---------------+
+//! |7|6|5|4|3|2|1|0|
+//! +---------------+
+//! |x|x|x|x|x|R|A|I|
+//! +---------------+
+//!
+//! Flags:
+//! I - Init
+//! A - Ack
+//! R - Reset
+//!
+//! The CRC is left out entirely with [`CrcKind::None`].
+//!
+//! Max Frame Size: 1510
+//! Max MTU: 1500
+//! Max On-the-wire length: 1516 (MFS + Overhead Byte (OHB) + Kind Byte + End of packet (EOP))
+
 use cobs::decode_in_place_with_sentinel;
-use crctab::compute_crc32;
+use crctab::{compute_crc32, compute_crc32c};
 use embedded_hal::delay::DelayNs;
+use embedded_io::{Error, ErrorKind};
 use heapless::{Deque, Vec};
 
+use super::LinkObserver;
+
 mod crctab;
 
-mod flags {
+pub mod flags {
     pub const INIT: u8 = 0x01;
     pub const ACK: u8 = 0x02;
     pub const RESET: u8 = 0x04;
 }
 
-/// ZSerial Frame Format
-///
-/// Using COBS
-///
-/// +-+-+----+------------+--------+-+
-/// |O|H|XXXX|ZZZZ....ZZZZ|CCCCCCCC|0|
-/// +-+----+------------+--------+-+
-/// |O| |Len |   Data     |  CRC32 |C|
-/// +-+-+-2--+----N-------+---4----+-+
-///
-/// Header: 1byte
-/// +---------------+
-/// |7|6|5|4|3|2|1|0|
-/// +---------------+
-/// |x|x|x|x|x|R|A|I|
-/// +---------------+
-///
-/// Flags:
-/// I - Init
-/// A - Ack
-/// R - Reset
-///
-/// Max Frame Size: 1510
-/// Max MTU: 1500
-/// Max On-the-wire length: 1516 (MFS + Overhead Byte (OHB) + Kind Byte + End of packet (EOP))
-
-const COBS_BUF_SIZE: usize = 1517;
+pub const COBS_BUF_SIZE: usize = 1517;
+pub(crate) const SERIAL_MAX_MTU: usize = 1500;
+/// Bytes a frame needs on the wire on top of its payload
+const SERIAL_FRAME_OVERHEAD: usize = COBS_BUF_SIZE - SERIAL_MAX_MTU;
 const SERIAL_CONNECT_THROTTLE_TIME_MS: u32 = 250;
 
 const KIND_FIELD_LEN: usize = 1;
 const LEN_FIELD_LEN: usize = 2;
 const CRC32_LEN: usize = 4;
 
-pub(crate) fn deserialize_from(source: &mut [u8]) -> Result<(usize, u8), super::LinkError> {
-    decode_in_place_with_sentinel(source, 0)?;
+/// Checksum appended to every serial frame. Both ends of the link must agree on it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CrcKind {
+    #[default]
+    Crc32,
+    /// Castagnoli polynomial
+    Crc32c,
+    /// No checksum at all, for links that already guarantee integrity. The
+    /// link then advertises itself as reliable.
+    None,
+}
+
+impl CrcKind {
+    pub fn compute(&self, data: &[u8]) -> u32 {
+        match self {
+            CrcKind::Crc32 => compute_crc32(data),
+            CrcKind::Crc32c => compute_crc32c(data),
+            CrcKind::None => 0,
+        }
+    }
+
+    /// Bytes the checksum takes at the end of a frame.
+    pub fn size(&self) -> usize {
+        match self {
+            CrcKind::Crc32 | CrcKind::Crc32c => CRC32_LEN,
+            CrcKind::None => 0,
+        }
+    }
+}
+
+pub(crate) fn deserialize_from(
+    source: &mut [u8],
+    crc: CrcKind,
+) -> Result<(usize, u8), super::LinkError> {
+    let decoded = decode_in_place_with_sentinel(source, 0)?;
+    if decoded < KIND_FIELD_LEN + LEN_FIELD_LEN {
+        return Err(super::LinkError::MalformedFrame);
+    }
 
     let header = source[0];
 
     let wire_size = u16::from_le_bytes([source[1], source[2]]) as usize;
 
-    if wire_size + KIND_FIELD_LEN + LEN_FIELD_LEN + CRC32_LEN > source.len() {
-        return Err(super::LinkError::DecodeError(
-            cobs::DecodeError::TargetBufTooSmall,
-        ));
+    // The length field has to account for every decoded byte, a frame sent
+    // with another checksum mode fails here too
+    if KIND_FIELD_LEN + LEN_FIELD_LEN + wire_size + crc.size() != decoded {
+        return Err(super::LinkError::MalformedFrame);
+    }
+    if crc == CrcKind::None {
+        return Ok((wire_size, header));
     }
 
-    let compute_crc = compute_crc32(
+    let compute_crc = crc.compute(
         &source[KIND_FIELD_LEN + LEN_FIELD_LEN..KIND_FIELD_LEN + wire_size + LEN_FIELD_LEN],
     );
 
@@ -86,13 +134,27 @@ enum CodecState {
     Crc,
 }
 
-pub struct SerialIntf<RX, TX, Delay> {
+/// Serial link whose receive buffer holds `N` bytes of COBS-encoded frame.
+///
+/// `N` bounds the MTU advertised to the transport, so small-MTU deployments
+/// can shrink it below the default [`COBS_BUF_SIZE`]. `O` is told about
+/// link events, see [`SerialIntf::with_observer`].
+pub struct SerialIntf<RX, TX, Delay, const N: usize = COBS_BUF_SIZE, O = ()> {
     rx: RX,
     tx: TX,
 
     delay: Delay,
 
     codec_state: CodecState,
+    crc: CrcKind,
+    /// Rounds `connect` makes before giving up, `None` retries forever.
+    connect_attempts: Option<u32>,
+    /// Error kind the peripheral reports instead of blocking, see
+    /// [`SerialIntf::with_would_block`].
+    would_block: Option<ErrorKind>,
+    observer: O,
+
+    tx_buf: Vec<u8, N>,
 }
 
 impl<RX, TX, Delay> SerialIntf<RX, TX, Delay>
@@ -101,11 +163,24 @@ where
     TX: embedded_io::Write,
     Delay: DelayNs,
 {
-    pub fn name(&self) -> &'static str {
-        "Serial"
+    pub fn new(rx: RX, tx: TX, delay: Delay) -> Self {
+        Self::with_buffer_size(rx, tx, delay)
     }
+}
+
+impl<RX, TX, Delay, const N: usize> SerialIntf<RX, TX, Delay, N>
+where
+    RX: embedded_io::Read,
+    TX: embedded_io::Write,
+    Delay: DelayNs,
+{
+    /// Like `new`, for an interface with an `N` byte buffer, e.g.
+    /// `SerialIntf::<_, _, _, 256>::with_buffer_size(rx, tx, delay)`.
+    ///
+    /// Fails to compile when `N` cannot hold the framing overhead.
+    pub fn with_buffer_size(rx: RX, tx: TX, delay: Delay) -> Self {
+        let _ = Self::MTU;
 
-    pub fn new(rx: RX, tx: TX, delay: Delay) -> Self {
         Self {
             rx,
             tx,
@@ -113,23 +188,111 @@ where
             delay,
 
             codec_state: CodecState::Header,
+            crc: CrcKind::Crc32,
+            connect_attempts: None,
+            would_block: None,
+            observer: (),
+
+            tx_buf: Vec::new(),
+        }
+    }
+}
+
+impl<RX, TX, Delay, const N: usize, O> SerialIntf<RX, TX, Delay, N, O>
+where
+    RX: embedded_io::Read,
+    TX: embedded_io::Write,
+    Delay: DelayNs,
+    O: LinkObserver,
+{
+    /// Largest payload a frame can carry with an `N` byte buffer.
+    pub const MTU: usize = {
+        assert!(
+            N > SERIAL_FRAME_OVERHEAD,
+            "serial buffer too small for the framing overhead"
+        );
+        let mtu = N - SERIAL_FRAME_OVERHEAD;
+        if mtu < SERIAL_MAX_MTU {
+            mtu
+        } else {
+            SERIAL_MAX_MTU
+        }
+    };
+
+    pub fn name(&self) -> &'static str {
+        "Serial"
+    }
+
+    /// Reports link events to `observer` instead of the current one.
+    pub fn with_observer<P: LinkObserver>(self, observer: P) -> SerialIntf<RX, TX, Delay, N, P> {
+        SerialIntf {
+            rx: self.rx,
+            tx: self.tx,
+
+            delay: self.delay,
+
+            codec_state: self.codec_state,
+            crc: self.crc,
+            connect_attempts: self.connect_attempts,
+            would_block: self.would_block,
+            observer,
+
+            tx_buf: self.tx_buf,
         }
     }
 
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Selects the checksum used for outgoing frames and checked on incoming ones.
+    pub fn with_crc(mut self, crc: CrcKind) -> Self {
+        self.crc = crc;
+        self
+    }
+
+    pub fn crc(&self) -> CrcKind {
+        self.crc
+    }
+
+    /// Makes `connect` fail with [`super::LinkError::ConnectFailed`] once
+    /// `max_attempts` INITs were answered with a RESET.
+    pub fn with_connect_retries(mut self, max_attempts: u32) -> Self {
+        self.connect_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Retries the reads and writes failing with `kind`, like interrupted
+    /// ones. `embedded-io` has no `WouldBlock`, so a driver for a
+    /// non-blocking UART reports it under another kind, e.g.
+    /// `ErrorKind::Other` which `std::io::ErrorKind::WouldBlock` converts to.
+    pub fn with_would_block(mut self, kind: ErrorKind) -> Self {
+        self.would_block = Some(kind);
+        self
+    }
+
+    /// Whether a read or write failing with `kind` is simply retried.
+    fn is_retried(&self, kind: ErrorKind) -> bool {
+        kind == ErrorKind::Interrupted || Some(kind) == self.would_block
+    }
+
+    /// Appends `data` to the frame being staged in `tx_buf`.
+    fn stage(&mut self, data: &[u8]) -> Result<(), super::LinkError> {
+        self.tx_buf
+            .extend_from_slice(data)
+            .map_err(|_| super::LinkError::FrameTooLong)
+    }
+
     fn send_patch(&mut self, overhead: u8, data: &[u8]) -> Result<(), super::LinkError> {
-        self.tx
-            .write_all(&[overhead])
-            .map_err(|_| super::LinkError::IoError)?;
-        self.tx
-            .write_all(data)
-            .map_err(|_e| super::LinkError::IoError)
+        self.stage(&[overhead])?;
+        self.stage(data)
     }
 
     fn internal_send(&mut self, header: u8, data: &[u8]) -> Result<(), super::LinkError> {
         let bytes_len = data.len();
-        let crc = compute_crc32(data);
+        let crc = self.crc.compute(data).to_le_bytes();
         let len_bytes = (bytes_len as u16).to_le_bytes();
-        let crc_bytes = crc.to_le_bytes();
+        let crc_bytes = &crc[..self.crc.size()];
 
         let mut overhead = 1;
 
@@ -139,6 +302,7 @@ where
         let mut crc_start_idx = 0usize;
         let mut crc_idx = 0usize;
         self.codec_state = CodecState::Header;
+        self.tx_buf.clear();
 
         loop {
             match self.codec_state {
@@ -195,41 +359,30 @@ where
                         continue;
                     }
 
-                    if overhead == 0xff {
-                        let mut data_end_idx = data_start_idx + overhead as usize - 1;
-                        if !prev_data.is_empty() {
-                            data_end_idx -= prev_data.len();
-                            let mut send_data = Vec::<u8, 3>::new();
-                            while let Some(d) = prev_data.pop_front() {
-                                send_data.push(d).map_err(|_| super::LinkError::IoError)?;
-                            }
-                            self.send_patch(overhead, send_data.as_slice())?;
-                            self.tx
-                                .write_all(&data[data_start_idx..data_end_idx])
-                                .map_err(|_| super::LinkError::IoError)?;
-                        } else {
-                            self.send_patch(overhead, &data[data_start_idx..data_end_idx])?;
+                    // The pending run is what is left in `prev_data` followed by
+                    // `data[data_start_idx..data_idx]`.
+                    debug_assert_eq!(
+                        overhead as usize - 1,
+                        prev_data.len() + data_idx - data_start_idx
+                    );
+
+                    let full = overhead == 0xff;
+                    if full || data[data_idx] == 0x00 {
+                        let mut send_data = Vec::<u8, 3>::new();
+                        while let Some(d) = prev_data.pop_front() {
+                            send_data.push(d).map_err(|_| super::LinkError::IoError)?;
                         }
-                        data_start_idx = data_end_idx;
+                        self.send_patch(overhead, send_data.as_slice())?;
+                        self.stage(&data[data_start_idx..data_idx])?;
+                        data_start_idx = data_idx;
                         overhead = 1;
-                    } else if data[data_idx] == 0x00 {
-                        let mut data_end_idx = data_start_idx + overhead as usize - 1;
-                        if !prev_data.is_empty() {
-                            data_end_idx -= prev_data.len();
-                            let mut send_data = Vec::<u8, 3>::new();
-                            while let Some(d) = prev_data.pop_front() {
-                                send_data.push(d).map_err(|_| super::LinkError::IoError)?;
-                            }
-                            self.send_patch(overhead, send_data.as_slice())?;
-                            self.tx
-                                .write_all(&data[data_start_idx..data_end_idx])
-                                .map_err(|_| super::LinkError::IoError)?;
-                        } else {
-                            self.send_patch(overhead, &data[data_start_idx..data_end_idx])?;
+
+                        if full {
+                            // The current byte opens the next run
+                            continue;
                         }
                         // Skip
-                        data_start_idx = data_end_idx + 1;
-                        overhead = 1;
+                        data_start_idx += 1;
                     } else {
                         overhead += 1;
                     }
@@ -240,112 +393,117 @@ where
                     }
                 }
                 CodecState::Crc => {
-                    if overhead == 0xff {
-                        // if prev_data is not empty
-                        // there are no zero in data seq
-                        let mut crc_end_idx = crc_start_idx + overhead as usize - 1;
-                        if !prev_data.is_empty() {
-                            crc_end_idx = crc_end_idx - prev_data.len() - bytes_len;
-                            let mut send_data = Vec::<u8, 3>::new();
-                            while let Some(d) = prev_data.pop_front() {
-                                send_data.push(d).map_err(|_| super::LinkError::IoError)?;
-                            }
-                            self.send_patch(overhead, send_data.as_slice())?;
-                            self.tx
-                                .write_all(&data[data_start_idx..])
-                                .map_err(|_| super::LinkError::IoError)?;
-                            self.tx
-                                .write_all(&crc_bytes[crc_start_idx..crc_end_idx])
-                                .map_err(|_| super::LinkError::IoError)?;
-                        } else if data_start_idx < bytes_len {
-                            crc_end_idx = crc_end_idx - data[data_start_idx..].len();
-                            self.send_patch(overhead, &data[data_start_idx..])?;
-                            data_start_idx = bytes_len;
-                            self.tx
-                                .write_all(&crc_bytes[crc_start_idx..crc_end_idx])
-                                .map_err(|_| super::LinkError::IoError)?;
-                        } else {
-                            self.send_patch(overhead, &crc_bytes[crc_start_idx..crc_end_idx])?;
-                        }
-                        crc_start_idx = crc_end_idx;
-                        overhead = 1;
-                    } else if crc_bytes[crc_idx] == 0x00 {
-                        let mut crc_end_idx = crc_start_idx + overhead as usize - 1;
-                        if !prev_data.is_empty() {
-                            crc_end_idx = crc_end_idx - prev_data.len() - bytes_len;
-                            let mut send_data = Vec::<u8, 3>::new();
-                            while let Some(d) = prev_data.pop_front() {
-                                send_data.push(d).map_err(|_| super::LinkError::IoError)?;
-                            }
-                            self.send_patch(overhead, send_data.as_slice())?;
-                            self.tx
-                                .write_all(&data[data_start_idx..])
-                                .map_err(|_| super::LinkError::IoError)?;
-                            self.tx
-                                .write_all(&crc_bytes[crc_start_idx..crc_end_idx])
-                                .map_err(|_| super::LinkError::IoError)?;
-                        } else if data_start_idx < bytes_len {
-                            crc_end_idx = crc_end_idx - data[data_start_idx..].len();
-                            self.send_patch(overhead, &data[data_start_idx..])?;
-                            data_start_idx = bytes_len;
-                            self.tx
-                                .write_all(&crc_bytes[crc_start_idx..crc_end_idx])
-                                .map_err(|_| super::LinkError::IoError)?;
-                        } else {
-                            self.send_patch(overhead, &crc_bytes[crc_start_idx..crc_end_idx])?;
-                        }
-                        overhead = 1;
-                        // skip
-                        crc_start_idx = crc_end_idx + 1;
-                    } else {
-                        overhead += 1;
-                    }
+                    // The pending run is what is left in `prev_data` and
+                    // `data[data_start_idx..]`, followed by
+                    // `crc_bytes[crc_start_idx..crc_idx]`, so the run always
+                    // ends at `crc_idx` and no index needs to be derived
+                    // from `overhead`.
+                    debug_assert!(data_start_idx <= bytes_len && crc_start_idx <= crc_idx);
+                    debug_assert_eq!(
+                        overhead as usize - 1,
+                        prev_data.len() + (bytes_len - data_start_idx) + (crc_idx - crc_start_idx)
+                    );
 
-                    crc_idx += 1;
-
-                    if crc_idx >= 4 {
+                    if crc_idx >= crc_bytes.len() {
                         let mut send_data = Vec::<u8, 3>::new();
                         while let Some(d) = prev_data.pop_front() {
                             send_data.push(d).map_err(|_| super::LinkError::IoError)?;
                         }
-                        self.send_patch(overhead, &send_data.as_slice())?;
+                        self.send_patch(overhead, send_data.as_slice())?;
                         if data_start_idx < bytes_len {
-                            self.tx
-                                .write_all(&data[data_start_idx..])
-                                .map_err(|_| super::LinkError::IoError)?;
+                            self.stage(&data[data_start_idx..])?;
                         }
-                        if crc_start_idx < 4 {
-                            self.tx
-                                .write_all(&crc_bytes[crc_start_idx..])
-                                .map_err(|_| super::LinkError::IoError)?;
+                        if crc_start_idx < crc_bytes.len() {
+                            self.stage(&crc_bytes[crc_start_idx..])?;
                         }
                         break;
                     }
+
+                    let full = overhead == 0xff;
+                    if full || crc_bytes[crc_idx] == 0x00 {
+                        let mut send_data = Vec::<u8, 3>::new();
+                        while let Some(d) = prev_data.pop_front() {
+                            send_data.push(d).map_err(|_| super::LinkError::IoError)?;
+                        }
+                        self.send_patch(overhead, send_data.as_slice())?;
+                        self.stage(&data[data_start_idx..])?;
+                        data_start_idx = bytes_len;
+                        self.stage(&crc_bytes[crc_start_idx..crc_idx])?;
+                        crc_start_idx = crc_idx;
+                        overhead = 1;
+
+                        if full {
+                            // The current byte opens the next run
+                            continue;
+                        }
+                        // skip
+                        crc_start_idx += 1;
+                    } else {
+                        overhead += 1;
+                    }
+
+                    crc_idx += 1;
                 }
             }
         }
 
-        self.tx
-            .write_all(&[0])
-            .map_err(|_| super::LinkError::IoError)?;
-        self.tx.flush().map_err(|_| super::LinkError::IoError)?;
+        self.stage(&[0])?;
 
-        Ok(())
+        // The whole frame goes out at once so slow UARTs are not driven one
+        // COBS run at a time. A frame cut short is reported with the bytes
+        // that left, only a frame not started at all is safe to resend as is.
+        let mut written = 0;
+        let res = loop {
+            if written == self.tx_buf.len() {
+                match self.tx.flush() {
+                    Err(e) if self.is_retried(e.kind()) => continue,
+                    res => break res.map_err(|e| super::LinkError::Io(e.kind())),
+                }
+            }
+
+            match self.tx.write(&self.tx_buf[written..]) {
+                Ok(n) if n > 0 => written += n,
+                Err(e) if self.is_retried(e.kind()) => {}
+                _ if written > 0 => break Err(super::LinkError::PartialWrite { written }),
+                Ok(_) => break Err(super::LinkError::Io(ErrorKind::WriteZero)),
+                Err(e) => break Err(super::LinkError::Io(e.kind())),
+            }
+        };
+        self.tx_buf.clear();
+
+        if res.is_ok() {
+            self.observer.on_frame_sent(header, written);
+        }
+
+        res
+    }
+
+    fn read_byte(&mut self) -> Result<u8, super::LinkError> {
+        let mut byte = 0;
+        loop {
+            match self.rx.read(core::slice::from_mut(&mut byte)) {
+                Ok(0) => return Err(super::LinkError::Closed),
+                Ok(_) => return Ok(byte),
+                Err(e) if self.is_retried(e.kind()) => {}
+                Err(e) => return Err(super::LinkError::Io(e.kind())),
+            }
+        }
     }
 
+    /// Every read ends on a sentinel, so a frame failing its CRC or COBS
+    /// decoding leaves the next one intact. Only a frame too long for `buf`
+    /// has to be drained up to its sentinel before reporting the error.
     fn internal_read(&mut self, buf: &mut [u8]) -> Result<(usize, u8), super::LinkError> {
         let mut start_count = 0;
 
         // Read
         loop {
             if start_count == buf.len() {
-                return Ok((0, 0));
+                while self.read_byte()? != 0 {}
+                return Err(super::LinkError::FrameTooLong);
             }
 
-            self.rx
-                .read_exact(core::slice::from_mut(&mut buf[start_count]))
-                .map_err(|_| super::LinkError::IoError)?;
-
+            buf[start_count] = self.read_byte()?;
             if buf[start_count] == 0 {
                 break;
             }
@@ -358,7 +516,7 @@ where
         #[cfg(feature = "defmt")]
         defmt::trace!("recv {:X}", buf[..start_count]);
 
-        let (wire_size, head) = deserialize_from(&mut buf[0..start_count])?;
+        let (wire_size, head) = deserialize_from(&mut buf[0..start_count], self.crc)?;
         buf.copy_within(3..3 + wire_size, 0);
         Ok((wire_size, head))
     }
@@ -367,20 +525,50 @@ where
         self.internal_send(0, data)
     }
 
+    /// Sends `data` in a frame whose header carries `flags` (e.g. `RESET`),
+    /// as `send` does with an empty header.
+    pub fn send_with_flags(&mut self, flags: u8, data: &[u8]) -> Result<(), super::LinkError> {
+        self.internal_send(flags, data)
+    }
+
     pub fn recv(&mut self, buf: &mut [u8]) -> Result<usize, super::LinkError> {
         let (size, _) = self.internal_read(buf)?;
         Ok(size)
     }
 
+    /// Retries flushing the TX side every millisecond until it succeeds or
+    /// `timeout_ms` has elapsed.
+    pub fn drain(&mut self, timeout_ms: u32) -> Result<(), super::LinkError> {
+        let mut elapsed = 0;
+
+        loop {
+            match self.tx.flush() {
+                Ok(()) => return Ok(()),
+                Err(_) if elapsed < timeout_ms => {
+                    self.delay.delay_ms(1);
+                    elapsed += 1;
+                }
+                Err(_) => return Err(super::LinkError::Timeout),
+            }
+        }
+    }
+
     // pub fn recv_in_place(&mut self) -> Result<(&[u8], u8), super::LinkError> {
     //     self.internal_read_in_place()
     // }
 
     pub fn connect(&mut self) -> Result<(), super::LinkError> {
-        let mut buff = [0u8; COBS_BUF_SIZE];
+        let mut buff = [0u8; N];
+        let mut attempts = 0;
 
         loop {
+            if self.connect_attempts.is_some_and(|max| attempts >= max) {
+                return Err(super::LinkError::ConnectFailed);
+            }
+            attempts += 1;
+
             self.internal_send(flags::INIT, &[])?;
+            self.observer.on_init_sent();
             #[cfg(feature = "defmt")]
             defmt::debug!("Sent INIT");
 
@@ -389,8 +577,10 @@ where
             if header & (flags::ACK | flags::INIT) == flags::ACK | flags::INIT {
                 #[cfg(feature = "defmt")]
                 defmt::debug!("Connected");
+                self.observer.on_connect();
                 break;
             } else if header & flags::RESET == flags::RESET {
+                self.observer.on_reset();
                 self.delay.delay_ms(SERIAL_CONNECT_THROTTLE_TIME_MS);
                 #[cfg(feature = "defmt")]
                 defmt::debug!("Reset");
@@ -404,3 +594,554 @@ where
         Ok(())
     }
 }
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::link::mock::{MockSerial, MockWire, NoDelay};
+    use crate::link::LinkError;
+
+    #[test]
+    fn crc_less_frames_round_trip() {
+        let wire = RefCell::new(MockWire::new());
+        let mut intf = SerialIntf::new(MockSerial::new(&wire), MockSerial::new(&wire), NoDelay)
+            .with_crc(CrcKind::None);
+
+        for data in [&[][..], &[0x00, 0x01, 0x02][..], &[0x49; 300][..]] {
+            intf.send(data).unwrap();
+            let mut buf = [0u8; COBS_BUF_SIZE];
+            let len = intf.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], data);
+        }
+    }
+
+    #[test]
+    fn mismatched_crc_modes_fail_to_decode() {
+        for (tx_crc, rx_crc) in [
+            (CrcKind::None, CrcKind::Crc32),
+            (CrcKind::Crc32, CrcKind::None),
+        ] {
+            let wire = RefCell::new(MockWire::new());
+            let tx = MockSerial::new(&wire);
+            SerialIntf::new(tx, tx, NoDelay)
+                .with_crc(tx_crc)
+                .send(&[0x01, 0x02, 0x03])
+                .unwrap();
+
+            let mut intf = SerialIntf::new(tx, tx, NoDelay).with_crc(rx_crc);
+            let mut buf = [0u8; COBS_BUF_SIZE];
+            assert!(matches!(
+                intf.recv(&mut buf),
+                Err(LinkError::MalformedFrame)
+            ));
+        }
+    }
+
+    #[test]
+    fn crc32_and_crc32c_do_not_verify_each_other() {
+        for (tx_crc, rx_crc) in [
+            (CrcKind::Crc32c, CrcKind::Crc32),
+            (CrcKind::Crc32, CrcKind::Crc32c),
+        ] {
+            let wire = RefCell::new(MockWire::new());
+            let port = MockSerial::new(&wire);
+            SerialIntf::new(port, port, NoDelay)
+                .with_crc(tx_crc)
+                .send(&[0x01, 0x02, 0x03])
+                .unwrap();
+
+            let mut intf = SerialIntf::new(port, port, NoDelay).with_crc(rx_crc);
+            let mut buf = [0u8; COBS_BUF_SIZE];
+            assert!(matches!(intf.recv(&mut buf), Err(LinkError::CrcError)));
+        }
+    }
+
+    #[test]
+    fn small_buffer_interface_carries_its_mtu() {
+        let wire = RefCell::new(MockWire::new());
+        let port = MockSerial::new(&wire);
+        let mut intf = SerialIntf::<_, _, _, 256>::with_buffer_size(port, port, NoDelay);
+        assert_eq!(SerialIntf::<MockSerial, MockSerial, NoDelay, 256>::MTU, 239);
+
+        let data = [0x49; 239];
+        intf.send(&data).unwrap();
+        let mut buf = [0u8; 256];
+        assert_eq!(intf.recv(&mut buf).unwrap(), data.len());
+        assert_eq!(&buf[..data.len()], &data);
+    }
+
+    #[test]
+    fn frame_with_embedded_zeros_matches_the_golden_bytes() {
+        let wire = RefCell::new(MockWire::new());
+        let port = MockSerial::new(&wire);
+        let mut intf = SerialIntf::new(port, port, NoDelay);
+
+        // COBS of header 0, length 6, the data and its CRC32, then the sentinel
+        intf.send(&[0x01, 0x00, 0x02, 0x00, 0x00, 0x03]).unwrap();
+        let sent: std::vec::Vec<u8> = wire.borrow().iter().copied().collect();
+        assert_eq!(
+            sent,
+            [
+                0x01, 0x02, 0x06, 0x02, 0x01, 0x02, 0x02, 0x01, 0x06, 0x03, 0x85, 0x36, 0x6a, 0xfa,
+                0x00
+            ]
+        );
+    }
+
+    /// Sends `data` with `header` through `intf` and decodes the wire bytes
+    /// back with `deserialize_from`.
+    fn round_trip(
+        intf: &mut SerialIntf<MockSerial, MockSerial, NoDelay>,
+        wire: &RefCell<MockWire>,
+        header: u8,
+        data: &[u8],
+    ) {
+        intf.internal_send(header, data).unwrap();
+        let mut sent: std::vec::Vec<u8> = wire.borrow().iter().copied().collect();
+        wire.borrow_mut().clear();
+        assert_eq!(sent.iter().position(|&b| b == 0), Some(sent.len() - 1));
+
+        let (len, decoded_header) = deserialize_from(&mut sent, intf.crc()).unwrap();
+        assert_eq!(decoded_header, header);
+        assert_eq!(len, data.len());
+        assert_eq!(&sent[KIND_FIELD_LEN + LEN_FIELD_LEN..][..len], data);
+    }
+
+    #[test]
+    fn random_payloads_round_trip() {
+        // xorshift32, so failures reproduce
+        let mut state = 0x2545_f491u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        let mut seeds: std::vec::Vec<std::vec::Vec<u8>> = std::vec![
+            std::vec![],
+            std::vec![0x00],
+            std::vec![0x01, 0x00, 0x00],
+            std::vec![0x00; 300],
+            std::vec![0x49; 254],
+            std::vec![0x49; 253],
+            std::vec![0x49; 255],
+            std::vec![0x49; 508],
+            std::vec![0x49; SERIAL_MAX_MTU],
+            [&[0x49; 254][..], &[0x00]].concat(),
+        ];
+        for _ in 0..200 {
+            let len = next() as usize % (SERIAL_MAX_MTU + 1);
+            // Sparse zeros so that long runs show up too
+            let mut data: std::vec::Vec<u8> = (0..len)
+                .map(|_| match next() % 64 {
+                    0 => 0,
+                    n => n as u8,
+                })
+                .collect();
+            if next() % 4 == 0 {
+                data.extend_from_slice(&[0x00, 0x00]);
+                data.truncate(SERIAL_MAX_MTU);
+            }
+            seeds.push(data);
+        }
+
+        for crc in [CrcKind::Crc32, CrcKind::Crc32c, CrcKind::None] {
+            let wire = RefCell::new(MockWire::new());
+            let port = MockSerial::new(&wire);
+            let mut intf = SerialIntf::new(port, port, NoDelay).with_crc(crc);
+            for (i, data) in seeds.iter().enumerate() {
+                let header = [0, flags::INIT, flags::RESET][i % 3];
+                round_trip(&mut intf, &wire, header, data);
+            }
+        }
+    }
+
+    #[test]
+    fn zeros_in_the_crc_round_trip() {
+        // (mode, last two payload bytes, zero byte of the CRC), each after no
+        // prefix and after a 252 byte run that makes the COBS block fill up
+        // within the CRC. The top CRC32 byte is never zero with this table.
+        let cases = [
+            (CrcKind::Crc32, [[1, 2], [16, 104]], 0),
+            (CrcKind::Crc32, [[1, 1], [2, 4]], 1),
+            (CrcKind::Crc32, [[4, 74], [4, 131]], 2),
+            (CrcKind::Crc32c, [[1, 161], [1, 241]], 0),
+            (CrcKind::Crc32c, [[1, 26], [1, 42]], 1),
+            (CrcKind::Crc32c, [[1, 145], [2, 87]], 2),
+            (CrcKind::Crc32c, [[1, 17], [1, 46]], 3),
+        ];
+
+        for (crc, tails, zero_at) in cases {
+            let wire = RefCell::new(MockWire::new());
+            let port = MockSerial::new(&wire);
+            let mut intf = SerialIntf::new(port, port, NoDelay).with_crc(crc);
+            for (prefix, tail) in [0, 252].into_iter().zip(tails) {
+                let data = [&[0x49; 252][..prefix], &tail].concat();
+                assert_eq!(crc.compute(&data).to_le_bytes()[zero_at], 0);
+                round_trip(&mut intf, &wire, 0, &data);
+            }
+        }
+    }
+
+    #[test]
+    fn inconsistent_length_fields_are_malformed() {
+        // header, length and data, without CRC
+        let frames: [&[u8]; 4] = [
+            &[0x00, 0x05, 0x00, 0x01, 0x02],
+            &[0x00, 0x01, 0x00, 0x01, 0x02],
+            &[0x00, 0x02, 0x01, 0x01, 0x02],
+            &[0x00, 0x00],
+        ];
+        for frame in frames {
+            let mut source = [0u8; 16];
+            let len = cobs::encode(frame, &mut source);
+            let mut source = [&source[..len], &[0]].concat();
+            assert!(matches!(
+                deserialize_from(&mut source, CrcKind::None),
+                Err(LinkError::MalformedFrame)
+            ));
+        }
+
+        let mut source = [0u8; 16];
+        let len = cobs::encode(&[0x00, 0x02, 0x00, 0x01, 0x02], &mut source);
+        let mut source = [&source[..len], &[0]].concat();
+        assert_eq!(
+            deserialize_from(&mut source, CrcKind::None).unwrap(),
+            (2, 0)
+        );
+    }
+
+    #[test]
+    fn closed_wire_is_reported() {
+        let wire = RefCell::new(MockWire::new());
+        let port = MockSerial::new(&wire);
+        let mut intf = SerialIntf::new(port, port, NoDelay);
+        let mut buf = [0u8; COBS_BUF_SIZE];
+        assert!(matches!(intf.recv(&mut buf), Err(LinkError::Closed)));
+
+        // Closed halfway through a frame
+        intf.send(&[0x01, 0x02]).unwrap();
+        wire.borrow_mut().pop_back();
+        assert!(matches!(intf.recv(&mut buf), Err(LinkError::Closed)));
+    }
+
+    #[test]
+    fn frame_longer_than_the_buffer_is_reported() {
+        let wire = RefCell::new(MockWire::new());
+        let port = MockSerial::new(&wire);
+        let mut intf = SerialIntf::new(port, port, NoDelay);
+
+        intf.send(&[0x49; 32]).unwrap();
+        let mut buf = [0u8; 16];
+        assert!(matches!(intf.recv(&mut buf), Err(LinkError::FrameTooLong)));
+        assert!(wire.borrow().is_empty());
+    }
+
+    #[test]
+    fn connect_gives_up_after_the_configured_attempts() {
+        let rx = RefCell::new(MockWire::new());
+        let tx = RefCell::new(MockWire::new());
+
+        // The peer answers every INIT with a RESET
+        let mut peer = SerialIntf::new(MockSerial::new(&tx), MockSerial::new(&rx), NoDelay);
+        for _ in 0..3 {
+            peer.send_with_flags(flags::RESET, &[]).unwrap();
+        }
+
+        let mut intf = SerialIntf::new(MockSerial::new(&rx), MockSerial::new(&tx), NoDelay)
+            .with_connect_retries(3);
+        assert!(matches!(intf.connect(), Err(LinkError::ConnectFailed)));
+        assert!(rx.borrow().is_empty());
+    }
+
+    #[test]
+    fn reset_flagged_frame_round_trips() {
+        let wire = RefCell::new(MockWire::new());
+        let port = MockSerial::new(&wire);
+        let mut intf = SerialIntf::new(port, port, NoDelay);
+
+        intf.send_with_flags(flags::RESET, &[]).unwrap();
+        let mut buf = [0u8; COBS_BUF_SIZE];
+        assert_eq!(intf.internal_read(&mut buf).unwrap(), (0, flags::RESET));
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        inits: usize,
+        resets: usize,
+        connects: usize,
+        frames: usize,
+    }
+
+    impl LinkObserver for Counter {
+        fn on_init_sent(&mut self) {
+            self.inits += 1;
+        }
+
+        fn on_reset(&mut self) {
+            self.resets += 1;
+        }
+
+        fn on_connect(&mut self) {
+            self.connects += 1;
+        }
+
+        fn on_frame_sent(&mut self, _header: u8, _len: usize) {
+            self.frames += 1;
+        }
+    }
+
+    #[test]
+    fn observer_sees_the_connect_exchange() {
+        let rx = RefCell::new(MockWire::new());
+        let tx = RefCell::new(MockWire::new());
+
+        let mut peer = SerialIntf::new(MockSerial::new(&tx), MockSerial::new(&rx), NoDelay);
+        peer.send_with_flags(flags::RESET, &[]).unwrap();
+        peer.send_with_flags(flags::INIT | flags::ACK, &[]).unwrap();
+
+        let mut intf = SerialIntf::new(MockSerial::new(&rx), MockSerial::new(&tx), NoDelay)
+            .with_observer(Counter::default());
+        intf.connect().unwrap();
+
+        let counter = intf.observer();
+        assert_eq!(counter.inits, 2);
+        assert_eq!(counter.resets, 1);
+        assert_eq!(counter.connects, 1);
+        assert_eq!(counter.frames, 2);
+    }
+
+    /// Accepts `limit` bytes, then fails every write.
+    struct FailingWriter {
+        limit: usize,
+        written: usize,
+    }
+
+    impl embedded_io::ErrorType for FailingWriter {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            let n = buf.len().min(self.limit - self.written);
+            if n == 0 {
+                return Err(embedded_io::ErrorKind::Other);
+            }
+            self.written += n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn cut_short_frame_reports_the_bytes_written() {
+        let wire = RefCell::new(MockWire::new());
+        let tx = FailingWriter {
+            limit: 4,
+            written: 0,
+        };
+        let mut intf = SerialIntf::new(MockSerial::new(&wire), tx, NoDelay);
+        assert!(matches!(
+            intf.send(&[0x01; 10]),
+            Err(LinkError::PartialWrite { written: 4 })
+        ));
+
+        // Nothing left the link, the frame can be resent
+        let tx = FailingWriter {
+            limit: 0,
+            written: 0,
+        };
+        let mut intf = SerialIntf::new(MockSerial::new(&wire), tx, NoDelay);
+        assert!(matches!(
+            intf.send(&[0x01; 10]),
+            Err(LinkError::Io(embedded_io::ErrorKind::Other))
+        ));
+    }
+
+    /// Fails the reads with `kind` `failures` times before reading from the wire.
+    struct FlakyReader<'a> {
+        inner: MockSerial<'a>,
+        kind: embedded_io::ErrorKind,
+        failures: usize,
+    }
+
+    impl embedded_io::ErrorType for FlakyReader<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for FlakyReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(self.kind);
+            }
+            Ok(self.inner.read(buf).unwrap())
+        }
+    }
+
+    #[test]
+    fn interrupted_reads_are_retried() {
+        let wire = RefCell::new(MockWire::new());
+        let tx = MockSerial::new(&wire);
+        SerialIntf::new(tx, tx, NoDelay)
+            .send(&[0x01, 0x02])
+            .unwrap();
+
+        let rx = FlakyReader {
+            inner: MockSerial::new(&wire),
+            kind: embedded_io::ErrorKind::Interrupted,
+            failures: 1,
+        };
+        let mut intf = SerialIntf::new(rx, tx, NoDelay);
+        let mut buf = [0u8; COBS_BUF_SIZE];
+        assert_eq!(intf.recv(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], &[0x01, 0x02]);
+
+        let rx = FlakyReader {
+            inner: MockSerial::new(&wire),
+            kind: embedded_io::ErrorKind::BrokenPipe,
+            failures: 1,
+        };
+        let mut intf = SerialIntf::new(rx, tx, NoDelay);
+        assert!(matches!(
+            intf.recv(&mut buf),
+            Err(LinkError::Io(embedded_io::ErrorKind::BrokenPipe))
+        ));
+    }
+
+    /// Fails the writes with `kind` `failures` times before writing to the wire.
+    struct FlakyWriter<'a> {
+        inner: MockSerial<'a>,
+        kind: embedded_io::ErrorKind,
+        failures: usize,
+    }
+
+    impl embedded_io::ErrorType for FlakyWriter<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Write for FlakyWriter<'_> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(self.kind);
+            }
+            Ok(self.inner.write(buf).unwrap())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn would_block_reads_and_writes_are_retried() {
+        let would_block = embedded_io::ErrorKind::Other;
+        let wire = RefCell::new(MockWire::new());
+        let tx = FlakyWriter {
+            inner: MockSerial::new(&wire),
+            kind: would_block,
+            failures: 2,
+        };
+        SerialIntf::new(MockSerial::new(&wire), tx, NoDelay)
+            .with_would_block(would_block)
+            .send(&[0x01, 0x02])
+            .unwrap();
+
+        let rx = FlakyReader {
+            inner: MockSerial::new(&wire),
+            kind: would_block,
+            failures: 2,
+        };
+        let mut intf =
+            SerialIntf::new(rx, MockSerial::new(&wire), NoDelay).with_would_block(would_block);
+        let mut buf = [0u8; COBS_BUF_SIZE];
+        assert_eq!(intf.recv(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], &[0x01, 0x02]);
+
+        // Not retried unless configured
+        let tx = FlakyWriter {
+            inner: MockSerial::new(&wire),
+            kind: would_block,
+            failures: 1,
+        };
+        let mut intf = SerialIntf::new(MockSerial::new(&wire), tx, NoDelay);
+        assert!(matches!(
+            intf.send(&[0x01, 0x02]),
+            Err(LinkError::Io(embedded_io::ErrorKind::Other))
+        ));
+        assert!(wire.borrow().is_empty());
+    }
+
+    #[test]
+    fn reads_resynchronize_after_a_bad_frame() {
+        let wire = RefCell::new(MockWire::new());
+        let port = MockSerial::new(&wire);
+        let mut intf = SerialIntf::new(port, port, NoDelay);
+        let mut buf = [0u8; COBS_BUF_SIZE];
+
+        // A flipped payload byte fails the CRC
+        intf.send(&[0x01, 0x02, 0x03]).unwrap();
+        let corrupted = wire.borrow().len() - 6;
+        *wire.borrow_mut().iter_mut().nth(corrupted).unwrap() ^= 0x40;
+        intf.send(&[0x04, 0x05]).unwrap();
+        assert!(intf.recv(&mut buf).is_err());
+        assert_eq!(intf.recv(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], &[0x04, 0x05]);
+
+        // Noise longer than the buffer is dropped up to its sentinel
+        for _ in 0..32 {
+            wire.borrow_mut().push_back(0x49).unwrap();
+        }
+        wire.borrow_mut().push_back(0x00).unwrap();
+        intf.send(&[0x06]).unwrap();
+        assert!(matches!(
+            intf.recv(&mut buf[..16]),
+            Err(LinkError::FrameTooLong)
+        ));
+        assert_eq!(intf.recv(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 0x06);
+    }
+
+    /// Takes every byte but never manages to flush them.
+    struct StuckWriter;
+
+    impl embedded_io::ErrorType for StuckWriter {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Write for StuckWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Err(embedded_io::ErrorKind::TimedOut)
+        }
+    }
+
+    /// Adds up the nanoseconds it was asked to wait.
+    struct CountingDelay<'a>(&'a core::cell::Cell<u64>);
+
+    impl DelayNs for CountingDelay<'_> {
+        fn delay_ns(&mut self, ns: u32) {
+            self.0.set(self.0.get() + ns as u64);
+        }
+    }
+
+    #[test]
+    fn drain_gives_up_at_the_deadline() {
+        let wire = RefCell::new(MockWire::new());
+        let waited = core::cell::Cell::new(0);
+        let mut intf = SerialIntf::new(MockSerial::new(&wire), StuckWriter, CountingDelay(&waited));
+
+        assert!(matches!(intf.drain(5), Err(LinkError::Timeout)));
+        assert_eq!(waited.get(), 5_000_000);
+    }
+}
@@ -0,0 +1,60 @@
+//! TCP link over `std::net`, for running the client on a desktop host.
+//!
+//! TCP is a stream, so [`Link`] prefixes every transport message with its
+//! length as zenoh does on its TCP links.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use crate::Z_MAX_MTU;
+
+use super::{Endpoint, Link, LinkCapabilities, LinkError, LinkIntf, TransportCap, TransportFlow};
+
+pub struct TcpIntf {
+    stream: TcpStream,
+}
+
+impl TcpIntf {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, LinkError> {
+        let stream = TcpStream::connect(addr).map_err(|_| LinkError::ConnectFailed)?;
+        Ok(TcpIntf::new(stream))
+    }
+
+    pub fn new(stream: TcpStream) -> Self {
+        TcpIntf { stream }
+    }
+
+    pub fn name(&self) -> &'static str {
+        "Tcp"
+    }
+}
+
+impl Endpoint for TcpIntf {
+    type L = TcpIntf;
+
+    fn create_link_from_endpoint(ep: Self) -> Link<Self::L> {
+        Link {
+            intf: ep,
+            mtu: Z_MAX_MTU,
+            cap: LinkCapabilities::new(TransportCap::Unicast, TransportFlow::STREAM, true),
+        }
+    }
+}
+
+impl LinkIntf for TcpIntf {
+    fn open(&mut self) -> Result<(), LinkError> {
+        Ok(())
+    }
+
+    fn send(&mut self, msg: &[u8]) -> Result<(), LinkError> {
+        self.stream.write_all(msg).map_err(|_| LinkError::IoError)
+    }
+
+    /// Returns 0 once the peer closed the connection.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, LinkError> {
+        self.stream.read(buf).map_err(|e| match e.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => LinkError::Timeout,
+            _ => LinkError::IoError,
+        })
+    }
+}
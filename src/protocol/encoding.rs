@@ -0,0 +1,77 @@
+//! # Encoding
+//!
+//! Describes how a payload is to be interpreted, as a numeric ID from the
+//! zenoh registry optionally refined by a schema:
+//!
+//! ```text
+//! ~ id_s:z32      ~  -- id << 1 | S, where S==1 if a schema follows
+//! +---------------+
+//! ~ schema:<u8;z8>~  if S==1
+//! +---------------+
+//! ```
+//!
+//! ID 0 without schema is the default `zenoh/bytes`, which a Put does not
+//! send at all.
+
+use crate::{
+    iobuf::{Reader, Writer},
+    protocol::{ProtocolError, Varint},
+    transport::TransportError,
+};
+
+const FLAG_S: u32 = 0x01;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Encoding<'a> {
+    pub id: u16,
+    pub schema: Option<&'a str>,
+}
+
+impl<'a> Encoding<'a> {
+    pub const fn new(id: u16) -> Self {
+        Encoding { id, schema: None }
+    }
+
+    pub const fn with_schema(id: u16, schema: &'a str) -> Self {
+        Encoding {
+            id,
+            schema: Some(schema),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Encoding::default()
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        let mut id_s = (self.id as u32) << 1;
+        if self.schema.is_some() {
+            id_s |= FLAG_S;
+        }
+        Varint::<u32>::encode(writer, id_s)?;
+
+        if let Some(schema) = self.schema {
+            let len = u8::try_from(schema.len()).map_err(|_| ProtocolError::SchemaTooLong)?;
+            Varint::<u8>::encode(writer, len)?;
+            writer.write_exact(schema.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R) -> Result<Self, TransportError> {
+        let id_s = Varint::<u32>::decode(reader)?;
+        let id = u16::try_from(id_s >> 1).map_err(|_| ProtocolError::VarintOverflow)?;
+
+        let schema = if id_s & FLAG_S == FLAG_S {
+            let len = Varint::<u8>::decode(reader)? as usize;
+            let schema = reader.read_slice_in_place(len)?;
+            Some(core::str::from_utf8(schema).map_err(|_| ProtocolError::InvalidEncoding)?)
+        } else {
+            None
+        };
+
+        Ok(Encoding { id, schema })
+    }
+}
@@ -0,0 +1,218 @@
+//! # Key expression
+//!
+//! On the wire a key expression is a numeric scope, which is either 0 or an ID
+//! previously mapped through a Declare, optionally followed by a string suffix.
+//! The suffix presence is signalled by the N flag of the message carrying it.
+//!
+//! +---------------+
+//! ~ key_scope:z16 ~
+//! +---------------+
+//! ~  key_suffix   ~  if N==1 -- <u8;z16>
+//! +---------------+
+//!
+
+use crate::{
+    iobuf::{Reader, Writer},
+    protocol::{ProtocolError, Varint},
+    transport::TransportError,
+    Z_MAX_MTU,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyExpr<'a> {
+    pub id: u16,
+    pub suffix: &'a str,
+}
+
+impl<'a> KeyExpr<'a> {
+    /// Checks `s` against the key expression canonical form: non-empty chunks
+    /// separated by single `/`, no leading or trailing `/`, wildcards only as
+    /// whole `*` or `**` chunks, never `**/**` nor `**/*` (written `*/**`).
+    pub fn is_canonical(s: &str) -> bool {
+        if s.is_empty() || s.contains(['#', '?']) {
+            return false;
+        }
+
+        let mut prev = "";
+        for chunk in s.split('/') {
+            if chunk.is_empty() {
+                return false;
+            }
+            if chunk.contains('*') && chunk != "*" && chunk != "**" {
+                return false;
+            }
+            if prev == "**" && (chunk == "**" || chunk == "*") {
+                return false;
+            }
+            prev = chunk;
+        }
+
+        true
+    }
+
+    /// A key expression carried entirely as a string.
+    pub fn new(suffix: &'a str) -> Self {
+        KeyExpr { id: 0, suffix }
+    }
+
+    /// A key expression relative to the mapping `id`, `suffix` may be empty.
+    pub fn with_id(id: u16, suffix: &'a str) -> Self {
+        KeyExpr { id, suffix }
+    }
+
+    /// Whether the N flag must be set on the message carrying this key expression.
+    #[inline]
+    pub fn has_suffix(&self) -> bool {
+        !self.suffix.is_empty()
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        if self.suffix.len() > Z_MAX_MTU {
+            return Err(ProtocolError::KeyExprTooLong.into());
+        }
+
+        Varint::<u16>::encode(writer, self.id)?;
+        if self.has_suffix() {
+            Varint::<u16>::encode(writer, self.suffix.len() as u16)?;
+            writer.write_exact(self.suffix.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R, named: bool) -> Result<Self, TransportError> {
+        let id = Varint::<u16>::decode(reader)?;
+        let suffix = if named {
+            let len = Varint::<u16>::decode(reader)? as usize;
+            if len > Z_MAX_MTU {
+                return Err(ProtocolError::KeyExprTooLong.into());
+            }
+            let suffix = reader.read_slice_in_place(len)?;
+            core::str::from_utf8(suffix).map_err(|_| ProtocolError::InvalidKeyExpr)?
+        } else {
+            ""
+        };
+
+        Ok(KeyExpr { id, suffix })
+    }
+}
+
+impl<'a> TryFrom<&'a str> for KeyExpr<'a> {
+    type Error = ProtocolError;
+
+    fn try_from(s: &'a str) -> Result<Self, Self::Error> {
+        if s.len() > Z_MAX_MTU {
+            return Err(ProtocolError::KeyExprTooLong);
+        }
+        if !KeyExpr::is_canonical(s) {
+            return Err(ProtocolError::NonCanonicalKeyExpr);
+        }
+
+        Ok(KeyExpr::new(s))
+    }
+}
+
+/// Whether the key expressions `a` and `b` can match a common key.
+///
+/// `*` matches exactly one chunk and `**` any number of chunks, including none.
+/// Verbatim chunks (starting with `@`) are only ever matched by themselves.
+pub fn intersects(a: &str, b: &str) -> bool {
+    match (next_chunk(a), next_chunk(b)) {
+        (None, None) => true,
+        (Some(("**", ra)), _) => {
+            intersects(ra, b)
+                || next_chunk(b).is_some_and(|(cb, rb)| !is_verbatim(cb) && intersects(a, rb))
+        }
+        (_, Some(("**", rb))) => {
+            intersects(a, rb)
+                || next_chunk(a).is_some_and(|(ca, ra)| !is_verbatim(ca) && intersects(ra, b))
+        }
+        (Some((ca, ra)), Some((cb, rb))) => chunk_intersects(ca, cb) && intersects(ra, rb),
+        _ => false,
+    }
+}
+
+fn next_chunk(s: &str) -> Option<(&str, &str)> {
+    if s.is_empty() {
+        return None;
+    }
+
+    Some(s.split_once('/').unwrap_or((s, "")))
+}
+
+#[inline]
+fn is_verbatim(chunk: &str) -> bool {
+    chunk.starts_with('@')
+}
+
+fn chunk_intersects(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+
+    (a == "*" && !is_verbatim(b)) || (b == "*" && !is_verbatim(a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{SliceReader, SliceWriter};
+
+    fn round_trip(ke: KeyExpr) {
+        let mut buf = [0u8; 32];
+        let mut writer = SliceWriter::new(&mut buf);
+        ke.encode(&mut writer).unwrap();
+        let len = writer.len();
+
+        let reader = SliceReader::new(&buf[..len]);
+        assert_eq!(KeyExpr::decode(&reader, ke.has_suffix()).unwrap(), ke);
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn string_keyexpr_round_trips() {
+        round_trip(KeyExpr::try_from("demo/example").unwrap());
+    }
+
+    #[test]
+    fn id_and_suffix_round_trip() {
+        round_trip(KeyExpr::with_id(300, "/suffix"));
+        round_trip(KeyExpr::with_id(7, ""));
+    }
+
+    #[test]
+    fn canonical_keys_are_accepted() {
+        for ke in [
+            "demo",
+            "demo/example",
+            "demo/*",
+            "demo/**",
+            "a/*/c",
+            "*/**",
+            "@admin/x",
+        ] {
+            assert!(KeyExpr::is_canonical(ke), "{ke}");
+        }
+    }
+
+    #[test]
+    fn non_canonical_keys_are_rejected() {
+        for ke in [
+            "",
+            "/demo",
+            "demo/",
+            "demo//x",
+            "demo/x*",
+            "demo/**/**",
+            "demo/**/*",
+            "demo/a?",
+        ] {
+            assert!(!KeyExpr::is_canonical(ke), "{ke}");
+        }
+        assert!(matches!(
+            KeyExpr::try_from("demo/"),
+            Err(ProtocolError::NonCanonicalKeyExpr)
+        ));
+    }
+}
@@ -1,32 +1,121 @@
+use thiserror::Error;
+
 use crate::{
     iobuf::{Reader, Writer},
     transport::TransportError,
 };
 
+pub mod encoding;
+pub mod keyexpr;
+pub mod network;
+pub mod priority;
+pub mod reliability;
+pub mod timestamp;
 pub mod transport;
 pub mod whatami;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Error)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct ZenohID([u8; ZenohID::MAX_SIZE]);
+pub enum ProtocolError {
+    #[error("Invalid key expression")]
+    InvalidKeyExpr,
+    #[error("Key expression too long")]
+    KeyExprTooLong,
+    #[error("Non canonical key expression")]
+    NonCanonicalKeyExpr,
+    #[error("Reserved whatami value")]
+    BadWhatAmI(u8),
+    #[error("ZenohID must be 1 to 16 bytes")]
+    BadZenohIdLength(usize),
+    #[error("Varint does not fit its integer type")]
+    VarintOverflow,
+    #[error("Unsupported protocol version")]
+    VersionMismatch(u8),
+    #[error("Invalid encoding")]
+    InvalidEncoding,
+    #[error("Encoding schema longer than 255 bytes")]
+    SchemaTooLong,
+}
+
+/// A ZenohID of 1 to 16 bytes, little-endian.
+///
+/// The length is kept next to the bytes so an ID received with trailing zero
+/// bytes is not collapsed into the numerically equal shorter one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ZenohID {
+    bytes: [u8; ZenohID::MAX_SIZE],
+    len: u8,
+}
 
 impl ZenohID {
     pub const MAX_SIZE: usize = u128::BITS as usize / 8;
 
     #[inline]
     pub fn size(&self) -> usize {
-        Self::MAX_SIZE - (u128::from_le_bytes(self.0).leading_zeros() as usize / 8)
+        self.len as usize
     }
 
     #[inline]
     pub fn to_le_bytes(&self) -> [u8; ZenohID::MAX_SIZE] {
-        self.0
+        self.bytes
+    }
+
+    /// The bytes of the ID as they go on the wire.
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.size()]
+    }
+}
+
+impl Default for ZenohID {
+    fn default() -> Self {
+        ZenohID::from(0)
+    }
+}
+
+/// Orders by the numeric value of the ID, then by length so that IDs only
+/// differing by trailing zero bytes stay distinct as they are for `Eq`.
+impl Ord for ZenohID {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        u128::from_le_bytes(self.bytes)
+            .cmp(&u128::from_le_bytes(other.bytes))
+            .then(self.len.cmp(&other.len))
+    }
+}
+
+impl PartialOrd for ZenohID {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
+/// Uses the fewest bytes able to hold `id`, at least one.
 impl From<u128> for ZenohID {
     fn from(id: u128) -> Self {
-        ZenohID(id.to_le_bytes())
+        let len = (ZenohID::MAX_SIZE - id.leading_zeros() as usize / 8).max(1);
+        ZenohID {
+            bytes: id.to_le_bytes(),
+            len: len as u8,
+        }
+    }
+}
+
+/// Keeps the length of `bytes`, which must be 1 to 16 bytes long.
+impl TryFrom<&[u8]> for ZenohID {
+    type Error = ProtocolError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.is_empty() || bytes.len() > ZenohID::MAX_SIZE {
+            return Err(ProtocolError::BadZenohIdLength(bytes.len()));
+        }
+
+        let mut id = ZenohID {
+            bytes: [0; ZenohID::MAX_SIZE],
+            len: bytes.len() as u8,
+        };
+        id.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(id)
     }
 }
 
@@ -55,23 +144,94 @@ impl<T> Varint<T> {
         Ok(())
     }
 
-    pub fn decode<R: Reader>(reader: &mut R) -> Result<T, TransportError>
+    pub fn decode<R: Reader>(reader: &R) -> Result<T, TransportError>
     where
         T: num_traits::PrimInt,
     {
-        let size = core::mem::size_of::<T>();
+        // Every byte carries 7 bits, a u64 may take up to 10 of them.
+        let max_len = (core::mem::size_of::<T>() * 8).div_ceil(7);
 
         let mut value = T::zero();
         let mut shift = 0;
-        for _ in 0..size + 1 {
+        for _ in 0..max_len {
             let byte = reader.read_u8()?;
-            value = value | T::from(byte & 0x7F).unwrap() << shift;
+            let bits = T::from(byte & 0x7F).unwrap();
+            // Bits shifted past the width of T would be silently dropped.
+            if (bits << shift) >> shift != bits {
+                return Err(ProtocolError::VarintOverflow.into());
+            }
+            value = value | bits << shift;
             if byte & 0x80 == 0 {
-                break;
+                return Ok(value);
             }
             shift += 7;
         }
 
-        Ok(value)
+        Err(ProtocolError::VarintOverflow.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{SliceReader, SliceWriter};
+    use heapless::FnvIndexMap;
+
+    #[test]
+    fn varint_round_trip() {
+        let values = [
+            0u64,
+            1,
+            0x7f,
+            0x80,
+            0x3fff,
+            0x4000,
+            u32::MAX as u64,
+            u64::MAX,
+        ];
+
+        for value in values {
+            let mut buf = [0u8; 10];
+            let mut writer = SliceWriter::new(&mut buf);
+            Varint::<u64>::encode(&mut writer, value).unwrap();
+            let len = writer.len();
+
+            let reader = SliceReader::new(&buf[..len]);
+            assert_eq!(Varint::<u64>::decode(&reader).unwrap(), value);
+            assert_eq!(reader.remaining(), 0);
+        }
+    }
+
+    #[test]
+    fn varint_overflow() {
+        let reader = SliceReader::new(&[0xff, 0xff, 0x7f]);
+        assert!(matches!(
+            Varint::<u16>::decode(&reader),
+            Err(TransportError::ProtocolError(ProtocolError::VarintOverflow))
+        ));
+
+        let reader = SliceReader::new(&[0x80; 11]);
+        assert!(matches!(
+            Varint::<u64>::decode(&reader),
+            Err(TransportError::ProtocolError(ProtocolError::VarintOverflow))
+        ));
+    }
+
+    #[test]
+    fn zid_ordering_agrees_with_eq() {
+        let short = ZenohID::from(0x49);
+        let long = ZenohID::try_from(&[0x49, 0x00][..]).unwrap();
+        assert!(short < long);
+        assert!(long < ZenohID::from(0x4a));
+        assert_eq!(short.cmp(&ZenohID::from(0x49)), core::cmp::Ordering::Equal);
+
+        let mut peers = FnvIndexMap::<ZenohID, u8, 4>::new();
+        for (i, zid) in [short, long, ZenohID::from(0xbeef)].into_iter().enumerate() {
+            peers.insert(zid, i as u8).unwrap();
+        }
+        assert_eq!(peers.get(&ZenohID::from(0x49)), Some(&0));
+        assert_eq!(peers.get(&long), Some(&1));
+        assert_eq!(peers.get(&ZenohID::from(0xbeef)), Some(&2));
+        assert_eq!(peers.get(&ZenohID::from(0xbeee)), None);
     }
 }
@@ -0,0 +1,314 @@
+//! # Declare message
+//!
+//! Flags:
+//! - I: Interest       If I==1 then interest_id is present
+//! - X: Reserved
+//! - Z: Extension      If Z==1 then at least one extension is present
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|X|I| DECLARE |
+//! +-+-+-+---------+
+//! ~interest_id:z32~  if I==1
+//! +---------------+
+//! ~  [decl_exts]  ~  if Z==1
+//! +---------------+
+//! ~  declaration  ~
+//! +---------------+
+//!
+//! The interest ID is the request ID the declaration answers to, unsolicited
+//! declarations leave it out.
+//!
+//! # DeclareKeyExpr
+//!
+//! Flags:
+//! - N: Named          If N==1 then the key expr has name/suffix
+//! - X: Reserved
+//! - Z: Extension      If Z==1 then at least one extension is present
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|X|N| D_KEXPR |
+//! +---------------+
+//! ~  expr_id:z16  ~
+//! +---------------+
+//! ~ key_scope:z16 ~
+//! +---------------+
+//! ~  key_suffix   ~  if N==1 -- <u8;z16>
+//! +---------------+
+//! ~  [decl_exts]  ~  if Z==1
+//! +---------------+
+//!
+//! # DeclareSubscriber
+//!
+//! Flags:
+//! - N: Named          If N==1 then the key expr has name/suffix
+//! - M: Mapping        if M==1 then key expr mapping is the one declared by the sender, else it is the one declared by the receiver
+//! - Z: Extension      If Z==1 then at least one extension is present
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|M|N|  D_SUB  |
+//! +---------------+
+//! ~  subs_id:z32  ~
+//! +---------------+
+//! ~ key_scope:z16 ~
+//! +---------------+
+//! ~  key_suffix   ~  if N==1 -- <u8;z16>
+//! +---------------+
+//! ~  [decl_exts]  ~  if Z==1
+//! +---------------+
+//!
+
+use crate::{
+    iobuf::{Reader, Writer},
+    protocol::{keyexpr::KeyExpr, transport::frame, Varint},
+    transport::TransportError,
+};
+
+pub(crate) const Z_MID_N_DECLARE: u8 = 0x1e;
+pub(crate) const Z_MID_D_KEYEXPR: u8 = 0x00;
+pub(crate) const Z_MID_D_SUBSCRIBER: u8 = 0x02;
+
+pub mod flag {
+    pub const I: u8 = 1 << 5; // 0x20 Interest      if I==1 then the interest ID is present
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+pub mod decl_flag {
+    pub const N: u8 = 1 << 5; // 0x20 Named         if N==1 then the key expr has name/suffix
+    pub const M: u8 = 1 << 6; // 0x40 Mapping       if M==1 then the sender mapping is used
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Declare<'a> {
+    pub interest_id: Option<u32>,
+    pub body: DeclareBody<'a>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeclareBody<'a> {
+    DeclareKeyExpr(DeclareKeyExpr<'a>),
+    DeclareSubscriber(DeclareSubscriber<'a>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeclareKeyExpr<'a> {
+    pub id: u16,
+    pub keyexpr: KeyExpr<'a>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeclareSubscriber<'a> {
+    pub id: u32,
+    pub keyexpr: KeyExpr<'a>,
+}
+
+impl<'a> Declare<'a> {
+    pub fn new(body: DeclareBody<'a>) -> Self {
+        Declare {
+            interest_id: None,
+            body,
+        }
+    }
+
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_N_DECLARE;
+
+        if self.interest_id.is_some() {
+            header |= flag::I;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Encoding _Z_MID_N_DECLARE");
+
+        writer.write_u8(self.header())?;
+
+        if let Some(interest_id) = self.interest_id {
+            Varint::<u32>::encode(writer, interest_id)?;
+        }
+
+        match &self.body {
+            DeclareBody::DeclareKeyExpr(d) => d.encode(writer),
+            DeclareBody::DeclareSubscriber(d) => d.encode(writer),
+        }
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R, header: u8) -> Result<Self, TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Decoding _Z_MID_N_DECLARE");
+
+        let interest_id = if header & flag::I == flag::I {
+            Some(Varint::<u32>::decode(reader)?)
+        } else {
+            None
+        };
+
+        if header & flag::Z == flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        let decl_header = reader.read_u8()?;
+        let body = match decl_header & 0x1f {
+            Z_MID_D_KEYEXPR => {
+                DeclareBody::DeclareKeyExpr(DeclareKeyExpr::decode(reader, decl_header)?)
+            }
+            Z_MID_D_SUBSCRIBER => {
+                DeclareBody::DeclareSubscriber(DeclareSubscriber::decode(reader, decl_header)?)
+            }
+            _ => return Err(TransportError::UnexpectMsg),
+        };
+
+        Ok(Declare { interest_id, body })
+    }
+}
+
+impl<'a> DeclareKeyExpr<'a> {
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_D_KEYEXPR;
+
+        if self.keyexpr.has_suffix() {
+            header |= decl_flag::N;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        writer.write_u8(self.header())?;
+
+        Varint::<u16>::encode(writer, self.id)?;
+        self.keyexpr.encode(writer)?;
+
+        Ok(())
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R, header: u8) -> Result<Self, TransportError> {
+        let id = Varint::<u16>::decode(reader)?;
+        let keyexpr = KeyExpr::decode(reader, header & decl_flag::N == decl_flag::N)?;
+
+        if header & decl_flag::Z == decl_flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        Ok(DeclareKeyExpr { id, keyexpr })
+    }
+}
+
+impl<'a> DeclareSubscriber<'a> {
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_D_SUBSCRIBER;
+
+        if self.keyexpr.has_suffix() {
+            header |= decl_flag::N;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        writer.write_u8(self.header())?;
+
+        Varint::<u32>::encode(writer, self.id)?;
+        self.keyexpr.encode(writer)?;
+
+        Ok(())
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R, header: u8) -> Result<Self, TransportError> {
+        let id = Varint::<u32>::decode(reader)?;
+        let keyexpr = KeyExpr::decode(reader, header & decl_flag::N == decl_flag::N)?;
+
+        if header & decl_flag::Z == decl_flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        Ok(DeclareSubscriber { id, keyexpr })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{SliceReader, SliceWriter};
+
+    #[test]
+    fn interest_id_is_a_varint() {
+        let declare = Declare {
+            interest_id: Some(300),
+            body: DeclareBody::DeclareSubscriber(DeclareSubscriber {
+                id: 7,
+                keyexpr: KeyExpr::new("a"),
+            }),
+        };
+        let mut buf = [0u8; 16];
+        let mut writer = SliceWriter::new(&mut buf);
+        declare.encode(&mut writer).unwrap();
+        let len = writer.len();
+        assert_eq!(
+            &buf[..len],
+            &[
+                Z_MID_N_DECLARE | flag::I,
+                0xac, // 300 as a varint
+                0x02,
+                Z_MID_D_SUBSCRIBER | decl_flag::N,
+                0x07,
+                0x00,
+                0x01,
+                b'a',
+            ]
+        );
+
+        let reader = SliceReader::new(&buf[1..len]);
+        assert_eq!(Declare::decode(&reader, buf[0]).unwrap(), declare);
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn extensions_are_skipped() {
+        // A Z64 extension on the Declare and a ZBUF one on the declaration
+        let buf = [
+            Z_MID_N_DECLARE | flag::Z,
+            0x21,
+            0x05,
+            Z_MID_D_SUBSCRIBER | decl_flag::N | decl_flag::Z,
+            0x07,
+            0x00,
+            0x01,
+            b'a',
+            0x42,
+            0x01,
+            0xaa,
+        ];
+        let reader = SliceReader::new(&buf[1..]);
+        let declare = Declare::decode(&reader, buf[0]).unwrap();
+        assert_eq!(
+            declare.body,
+            DeclareBody::DeclareSubscriber(DeclareSubscriber {
+                id: 7,
+                keyexpr: KeyExpr::new("a"),
+            })
+        );
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn unknown_mandatory_extension_is_rejected() {
+        let buf = [
+            Z_MID_D_KEYEXPR | decl_flag::Z,
+            0x01,
+            0x00,
+            frame::ext::M | 0x0f,
+        ];
+        let reader = SliceReader::new(&buf[1..]);
+        assert!(matches!(
+            DeclareKeyExpr::decode(&reader, buf[0]),
+            Err(TransportError::UnknownMandatoryExtension(0x0f))
+        ));
+    }
+}
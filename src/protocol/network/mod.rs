@@ -0,0 +1,57 @@
+use declare::{Declare, Z_MID_N_DECLARE};
+use push::{Push, Z_MID_N_PUSH};
+use request::{Request, Z_MID_N_REQUEST};
+use response::{Response, ResponseFinal, Z_MID_N_RESPONSE, Z_MID_N_RESPONSE_FINAL};
+
+use crate::{
+    iobuf::{ChunkWriter, Reader, Writer},
+    transport::TransportError,
+};
+
+pub mod declare;
+pub mod push;
+pub mod request;
+pub mod response;
+
+// Zenoh messages at zenoh-network level
+#[derive(Debug, PartialEq, Eq)]
+pub enum NetworkMessage<'a> {
+    Push(Push<'a>),
+    Declare(Declare<'a>),
+    Request(Request<'a>),
+    Response(Response<'a>),
+    ResponseFinal(ResponseFinal),
+}
+
+impl<'a> NetworkMessage<'a> {
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        match self {
+            NetworkMessage::Push(m) => m.encode(writer),
+            NetworkMessage::Declare(m) => m.encode(writer),
+            NetworkMessage::Request(m) => m.encode(writer),
+            NetworkMessage::Response(m) => m.encode(writer),
+            NetworkMessage::ResponseFinal(m) => m.encode(writer),
+        }
+    }
+
+    /// Encodes the message without an intermediate buffer, `on_chunk` is called
+    /// with each piece of the encoding in order.
+    pub fn encode_chunks<F: FnMut(&[u8])>(&self, on_chunk: F) -> Result<(), TransportError> {
+        self.encode(&mut ChunkWriter::new(on_chunk))
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R) -> Result<Self, TransportError> {
+        let header = reader.read_u8()?;
+
+        match header & 0x1f {
+            Z_MID_N_PUSH => Ok(NetworkMessage::Push(Push::decode(reader, header)?)),
+            Z_MID_N_DECLARE => Ok(NetworkMessage::Declare(Declare::decode(reader, header)?)),
+            Z_MID_N_REQUEST => Ok(NetworkMessage::Request(Request::decode(reader, header)?)),
+            Z_MID_N_RESPONSE => Ok(NetworkMessage::Response(Response::decode(reader, header)?)),
+            Z_MID_N_RESPONSE_FINAL => Ok(NetworkMessage::ResponseFinal(ResponseFinal::decode(
+                reader, header,
+            )?)),
+            _ => Err(TransportError::UnknownMessageId(header)),
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! # Push message
+//!
+//! Flags:
+//! - N: Named          if N==1 then the key expr has name/suffix
+//! - M: Mapping        if M==1 then key expr mapping is the one declared by the sender, else it is the one declared by the receiver
+//! - Z: Extension      if Z==1 then at least one extension is present
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|M|N|  PUSH   |
+//! +-+-+-+---------+
+//! ~ key_scope:z16 ~
+//! +---------------+
+//! ~  key_suffix   ~  if N==1 -- <u8;z16>
+//! +---------------+
+//! ~  [push_exts]  ~  if Z==1
+//! +---------------+
+//! ~   PushBody    ~
+//! +---------------+
+//!
+//! # Put message
+//!
+//! Flags:
+//! - T: Timestamp      if T==1 then the timestamp if present
+//! - E: Encoding       if E==1 then the encoding is present
+//! - Z: Extension      if Z==1 then at least one extension is present
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|E|T|   PUT   |
+//! +-+-+-+---------+
+//! ~  timestamp    ~  if T==1
+//! +---------------+
+//! ~   encoding    ~  if E==1
+//! +---------------+
+//! ~  [put_exts]   ~  if Z==1
+//! +---------------+
+//! ~ pl: <u8;z32>  ~  -- Payload
+//! +---------------+
+//!
+
+use crate::{
+    iobuf::{Reader, Writer},
+    protocol::{
+        encoding::Encoding, keyexpr::KeyExpr, timestamp::Timestamp, transport::frame, Varint,
+    },
+    transport::TransportError,
+};
+
+pub(crate) const Z_MID_N_PUSH: u8 = 0x1d;
+pub(crate) const Z_MID_Z_PUT: u8 = 0x01;
+
+pub mod flag {
+    pub const N: u8 = 1 << 5; // 0x20 Named         if N==1 then the key expr has name/suffix
+    pub const M: u8 = 1 << 6; // 0x40 Mapping       if M==1 then the sender mapping is used
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+pub mod put_flag {
+    pub const T: u8 = 1 << 5; // 0x20 Timestamp     if T==1 then the timestamp is present
+    pub const E: u8 = 1 << 6; // 0x40 Encoding      if E==1 then the encoding is present
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Push<'a> {
+    pub keyexpr: KeyExpr<'a>,
+    pub body: PushBody<'a>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushBody<'a> {
+    Put(Put<'a>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Put<'a> {
+    pub timestamp: Option<Timestamp>,
+    /// Only sent when it is not the default encoding.
+    pub encoding: Encoding<'a>,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Push<'a> {
+    pub fn new(keyexpr: KeyExpr<'a>, payload: &'a [u8]) -> Self {
+        Self::with_encoding(keyexpr, payload, Encoding::default())
+    }
+
+    pub fn with_encoding(keyexpr: KeyExpr<'a>, payload: &'a [u8], encoding: Encoding<'a>) -> Self {
+        Push {
+            keyexpr,
+            body: PushBody::Put(Put {
+                timestamp: None,
+                encoding,
+                payload,
+            }),
+        }
+    }
+
+    pub fn with_timestamp(keyexpr: KeyExpr<'a>, payload: &'a [u8], timestamp: Timestamp) -> Self {
+        let mut put = Put::new(payload);
+        put.timestamp = Some(timestamp);
+        Push {
+            keyexpr,
+            body: PushBody::Put(put),
+        }
+    }
+
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_N_PUSH;
+
+        if self.keyexpr.has_suffix() {
+            header |= flag::N;
+        }
+        // Numeric scopes only ever come from our own declarations.
+        if self.keyexpr.id != 0 {
+            header |= flag::M;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Encoding _Z_MID_N_PUSH");
+
+        let header = self.header();
+
+        writer.write_u8(header)?;
+
+        self.keyexpr.encode(writer)?;
+
+        match &self.body {
+            PushBody::Put(put) => put.encode(writer),
+        }
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R, header: u8) -> Result<Self, TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Decoding _Z_MID_N_PUSH");
+
+        let keyexpr = KeyExpr::decode(reader, header & flag::N == flag::N)?;
+
+        if header & flag::Z == flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        let body_header = reader.read_u8()?;
+        let body = match body_header & 0x1f {
+            Z_MID_Z_PUT => PushBody::Put(Put::decode(reader, body_header)?),
+            _ => return Err(TransportError::UnexpectMsg),
+        };
+
+        Ok(Push { keyexpr, body })
+    }
+}
+
+impl<'a> Put<'a> {
+    pub fn new(payload: &'a [u8]) -> Self {
+        Put {
+            timestamp: None,
+            encoding: Encoding::default(),
+            payload,
+        }
+    }
+
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_Z_PUT;
+
+        if self.timestamp.is_some() {
+            header |= put_flag::T;
+        }
+        if !self.encoding.is_default() {
+            header |= put_flag::E;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        writer.write_u8(self.header())?;
+
+        if let Some(timestamp) = &self.timestamp {
+            timestamp.encode(writer)?;
+        }
+        if !self.encoding.is_default() {
+            self.encoding.encode(writer)?;
+        }
+
+        Varint::<u32>::encode(writer, self.payload.len() as u32)?;
+        writer.write_exact(self.payload)?;
+
+        Ok(())
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R, header: u8) -> Result<Self, TransportError> {
+        let timestamp = if header & put_flag::T == put_flag::T {
+            Some(Timestamp::decode(reader)?)
+        } else {
+            None
+        };
+
+        let encoding = if header & put_flag::E == put_flag::E {
+            Encoding::decode(reader)?
+        } else {
+            Encoding::default()
+        };
+
+        if header & put_flag::Z == put_flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        let len = Varint::<u32>::decode(reader)? as usize;
+        let payload = reader.read_slice_in_place(len)?;
+
+        Ok(Put {
+            timestamp,
+            encoding,
+            payload,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{SliceReader, SliceWriter};
+    use crate::protocol::ZenohID;
+
+    fn encode(push: &Push, buf: &mut [u8]) -> usize {
+        let mut writer = SliceWriter::new(buf);
+        push.encode(&mut writer).unwrap();
+        writer.len()
+    }
+
+    #[test]
+    fn put_wire_layout() {
+        let push = Push::new(KeyExpr::try_from("demo/test").unwrap(), b"hi");
+        let mut buf = [0u8; 32];
+        let len = encode(&push, &mut buf);
+        assert_eq!(
+            &buf[..len],
+            &[
+                Z_MID_N_PUSH | flag::N,
+                0x00, // scope
+                0x09, // suffix length
+                b'd',
+                b'e',
+                b'm',
+                b'o',
+                b'/',
+                b't',
+                b'e',
+                b's',
+                b't',
+                Z_MID_Z_PUT,
+                0x02, // payload length
+                b'h',
+                b'i',
+            ]
+        );
+
+        let reader = SliceReader::new(&buf[1..len]);
+        assert_eq!(Push::decode(&reader, buf[0]).unwrap(), push);
+    }
+
+    #[test]
+    fn encoding_id_is_sent_after_the_put_header() {
+        let ke = KeyExpr::try_from("demo").unwrap();
+        let mut buf = [0u8; 32];
+
+        // header, scope, suffix length and "demo" come first
+        let len = encode(&Push::new(ke, b"x"), &mut buf);
+        assert_eq!(&buf[7..len], &[Z_MID_Z_PUT, 0x01, b'x']);
+
+        let push = Push::with_encoding(ke, b"x", Encoding::new(7));
+        let len = encode(&push, &mut buf);
+        assert_eq!(
+            &buf[7..len],
+            &[Z_MID_Z_PUT | put_flag::E, 7 << 1, 0x01, b'x']
+        );
+
+        let reader = SliceReader::new(&buf[1..len]);
+        assert_eq!(Push::decode(&reader, buf[0]).unwrap(), push);
+    }
+
+    #[test]
+    fn encoding_schema_round_trips() {
+        let encoding = Encoding::with_schema(5, "utf-8");
+        let push = Push::with_encoding(KeyExpr::try_from("demo").unwrap(), b"x", encoding);
+        let mut buf = [0u8; 32];
+        let len = encode(&push, &mut buf);
+        assert_eq!(&buf[8..15], &[5 << 1 | 1, 5, b'u', b't', b'f', b'-', b'8']);
+
+        let reader = SliceReader::new(&buf[1..len]);
+        assert_eq!(Push::decode(&reader, buf[0]).unwrap(), push);
+    }
+
+    #[test]
+    fn timestamp_is_sent_before_the_payload() {
+        let ts = Timestamp::new(0x0102, ZenohID::from(0xbeef));
+        let push = Push::with_timestamp(KeyExpr::try_from("demo").unwrap(), b"x", ts);
+        let mut buf = [0u8; 32];
+        let len = encode(&push, &mut buf);
+        assert_eq!(
+            &buf[7..len],
+            &[
+                Z_MID_Z_PUT | put_flag::T,
+                0x82,
+                0x02,
+                0x02,
+                0xef,
+                0xbe,
+                0x01,
+                b'x'
+            ]
+        );
+
+        let reader = SliceReader::new(&buf[1..len]);
+        let decoded = Push::decode(&reader, buf[0]).unwrap();
+        let PushBody::Put(put) = &decoded.body;
+        assert_eq!(put.timestamp, Some(ts));
+    }
+
+    #[test]
+    fn extensions_are_skipped() {
+        let ke = KeyExpr::try_from("demo").unwrap();
+        let mut buf = [0u8; 32];
+        let len = encode(&Push::new(ke, b"x"), &mut buf);
+
+        // A Z64 extension on the Push and a unit one on the Put
+        let mut with_exts = [0u8; 32];
+        with_exts[..7].copy_from_slice(&buf[..7]);
+        with_exts[0] |= flag::Z;
+        with_exts[7..9].copy_from_slice(&[0x21, 0x05]);
+        with_exts[9] = Z_MID_Z_PUT | put_flag::Z;
+        with_exts[10] = 0x02;
+        with_exts[11..len + 3].copy_from_slice(&buf[8..len]);
+
+        let reader = SliceReader::new(&with_exts[1..len + 3]);
+        assert_eq!(
+            Push::decode(&reader, with_exts[0]).unwrap(),
+            Push::new(ke, b"x")
+        );
+        assert_eq!(reader.remaining(), 0);
+    }
+}
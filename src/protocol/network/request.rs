@@ -0,0 +1,206 @@
+//! # Request message
+//!
+//! Flags:
+//! - N: Named          if N==1 then the key expr has name/suffix
+//! - M: Mapping        if M==1 then key expr mapping is the one declared by the sender, else it is the one declared by the receiver
+//! - Z: Extension      if Z==1 then at least one extension is present
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|M|N| Request |
+//! +-+-+-+---------+
+//! ~ request_id:z32~
+//! +---------------+
+//! ~ key_scope:z16 ~
+//! +---------------+
+//! ~  key_suffix   ~  if N==1 -- <u8;z16>
+//! +---------------+
+//! ~   [req_exts]  ~  if Z==1
+//! +---------------+
+//! ~  RequestBody  ~
+//! +---------------+
+//!
+//! # Query message
+//!
+//! Flags:
+//! - C: Consolidation  if C==1 then consolidation is present
+//! - P: Parameters     If P==1 then the parameters are present
+//! - Z: Extension      If Z==1 then at least one extension is present
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|P|C|  QUERY  |
+//! +-+-+-+---------+
+//! % consolidation %  if C==1
+//! +---------------+
+//! ~ ps: <u8;z16>  ~  if P==1
+//! +---------------+
+//! ~  [qry_exts]   ~  if Z==1
+//! +---------------+
+//!
+
+use crate::{
+    iobuf::{Reader, Writer},
+    protocol::{keyexpr::KeyExpr, transport::frame, ProtocolError, Varint},
+    transport::TransportError,
+};
+
+pub(crate) const Z_MID_N_REQUEST: u8 = 0x1c;
+pub(crate) const Z_MID_Z_QUERY: u8 = 0x03;
+
+pub mod flag {
+    pub const N: u8 = 1 << 5; // 0x20 Named         if N==1 then the key expr has name/suffix
+    pub const M: u8 = 1 << 6; // 0x40 Mapping       if M==1 then the sender mapping is used
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+pub mod query_flag {
+    pub const C: u8 = 1 << 5; // 0x20 Consolidation if C==1 then the consolidation is present
+    pub const P: u8 = 1 << 6; // 0x40 Parameters    if P==1 then the parameters are present
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Request<'a> {
+    pub id: u32,
+    pub keyexpr: KeyExpr<'a>,
+    pub body: RequestBody<'a>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestBody<'a> {
+    Query(Query<'a>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Query<'a> {
+    pub parameters: &'a str,
+}
+
+impl<'a> Request<'a> {
+    pub fn new(id: u32, keyexpr: KeyExpr<'a>, parameters: &'a str) -> Self {
+        Request {
+            id,
+            keyexpr,
+            body: RequestBody::Query(Query { parameters }),
+        }
+    }
+
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_N_REQUEST;
+
+        if self.keyexpr.has_suffix() {
+            header |= flag::N;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Encoding _Z_MID_N_REQUEST");
+
+        writer.write_u8(self.header())?;
+
+        Varint::<u32>::encode(writer, self.id)?;
+        self.keyexpr.encode(writer)?;
+
+        match &self.body {
+            RequestBody::Query(query) => query.encode(writer),
+        }
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R, header: u8) -> Result<Self, TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Decoding _Z_MID_N_REQUEST");
+
+        let id = Varint::<u32>::decode(reader)?;
+        let keyexpr = KeyExpr::decode(reader, header & flag::N == flag::N)?;
+
+        if header & flag::Z == flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        let body_header = reader.read_u8()?;
+        let body = match body_header & 0x1f {
+            Z_MID_Z_QUERY => RequestBody::Query(Query::decode(reader, body_header)?),
+            _ => return Err(TransportError::UnexpectMsg),
+        };
+
+        Ok(Request { id, keyexpr, body })
+    }
+}
+
+impl<'a> Query<'a> {
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_Z_QUERY;
+
+        if !self.parameters.is_empty() {
+            header |= query_flag::P;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        let header = self.header();
+
+        writer.write_u8(header)?;
+
+        if header & query_flag::P == query_flag::P {
+            Varint::<u16>::encode(writer, self.parameters.len() as u16)?;
+            writer.write_exact(self.parameters.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R, header: u8) -> Result<Self, TransportError> {
+        if header & query_flag::C == query_flag::C {
+            let _consolidation = reader.read_u8()?;
+        }
+
+        let parameters = if header & query_flag::P == query_flag::P {
+            let len = Varint::<u16>::decode(reader)? as usize;
+            let parameters = reader.read_slice_in_place(len)?;
+            core::str::from_utf8(parameters).map_err(|_| ProtocolError::InvalidKeyExpr)?
+        } else {
+            ""
+        };
+
+        if header & query_flag::Z == query_flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        Ok(Query { parameters })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{SliceReader, SliceWriter};
+
+    #[test]
+    fn query_wire_layout() {
+        let request = Request::new(1, KeyExpr::try_from("demo/**").unwrap(), "arg=1");
+        let mut buf = [0u8; 32];
+        let mut writer = SliceWriter::new(&mut buf);
+        request.encode(&mut writer).unwrap();
+        let len = writer.len();
+
+        // header, request ID, scope, suffix length, then the Query
+        let expected = [
+            &[Z_MID_N_REQUEST | flag::N, 0x01, 0x00, 0x07][..],
+            b"demo/**",
+            &[Z_MID_Z_QUERY | query_flag::P, 0x05],
+            b"arg=1",
+        ]
+        .concat();
+        assert_eq!(&buf[..len], &expected[..]);
+
+        let reader = SliceReader::new(&buf[1..len]);
+        assert_eq!(Request::decode(&reader, buf[0]).unwrap(), request);
+        assert_eq!(reader.remaining(), 0);
+    }
+}
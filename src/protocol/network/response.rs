@@ -0,0 +1,218 @@
+//! # Response message
+//!
+//! Flags:
+//! - N: Named          if N==1 then the key expr has name/suffix
+//! - M: Mapping        if M==1 then key expr mapping is the one declared by the sender, else it is the one declared by the receiver
+//! - Z: Extension      if Z==1 then at least one extension is present
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|M|N| Response|
+//! +-+-+-+---------+
+//! ~ request_id:z32~
+//! +---------------+
+//! ~ key_scope:z16 ~
+//! +---------------+
+//! ~  key_suffix   ~  if N==1 -- <u8;z16>
+//! +---------------+
+//! ~  [reply_exts] ~  if Z==1
+//! +---------------+
+//! ~ ResponseBody  ~
+//! +---------------+
+//!
+//! # Reply message
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|X|C|  REPLY  |
+//! +-+-+-+---------+
+//! % consolidation %  if C==1
+//! +---------------+
+//! ~  [repl_exts]  ~  if Z==1
+//! +---------------+
+//! ~   ReplyBody   ~  -- Put
+//! +---------------+
+//!
+//! # ResponseFinal message
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|X|X| ResFinal|
+//! +-+-+-+---------+
+//! ~ request_id:z32~
+//! +---------------+
+//! ~  [reply_exts] ~  if Z==1
+//! +---------------+
+//!
+
+use crate::{
+    iobuf::{Reader, Writer},
+    protocol::{keyexpr::KeyExpr, transport::frame, Varint},
+    transport::TransportError,
+};
+
+use super::push::{Put, Z_MID_Z_PUT};
+
+pub(crate) const Z_MID_N_RESPONSE: u8 = 0x1b;
+pub(crate) const Z_MID_N_RESPONSE_FINAL: u8 = 0x1a;
+pub(crate) const Z_MID_Z_REPLY: u8 = 0x04;
+
+pub mod flag {
+    pub const N: u8 = 1 << 5; // 0x20 Named         if N==1 then the key expr has name/suffix
+    pub const M: u8 = 1 << 6; // 0x40 Mapping       if M==1 then the sender mapping is used
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+pub mod reply_flag {
+    pub const C: u8 = 1 << 5; // 0x20 Consolidation if C==1 then the consolidation is present
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Response<'a> {
+    pub request_id: u32,
+    pub keyexpr: KeyExpr<'a>,
+    pub body: ResponseBody<'a>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResponseBody<'a> {
+    Reply(Put<'a>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ResponseFinal {
+    pub request_id: u32,
+}
+
+impl<'a> Response<'a> {
+    pub fn new(request_id: u32, keyexpr: KeyExpr<'a>, payload: &'a [u8]) -> Self {
+        Response {
+            request_id,
+            keyexpr,
+            body: ResponseBody::Reply(Put::new(payload)),
+        }
+    }
+
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_N_RESPONSE;
+
+        if self.keyexpr.has_suffix() {
+            header |= flag::N;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Encoding _Z_MID_N_RESPONSE");
+
+        writer.write_u8(self.header())?;
+
+        Varint::<u32>::encode(writer, self.request_id)?;
+        self.keyexpr.encode(writer)?;
+
+        match &self.body {
+            ResponseBody::Reply(put) => {
+                writer.write_u8(Z_MID_Z_REPLY)?;
+                put.encode(writer)
+            }
+        }
+    }
+
+    pub fn decode<R: Reader>(reader: &'a R, header: u8) -> Result<Self, TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Decoding _Z_MID_N_RESPONSE");
+
+        let request_id = Varint::<u32>::decode(reader)?;
+        let keyexpr = KeyExpr::decode(reader, header & flag::N == flag::N)?;
+
+        if header & flag::Z == flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        let reply_header = reader.read_u8()?;
+        if reply_header & 0x1f != Z_MID_Z_REPLY {
+            return Err(TransportError::UnexpectMsg);
+        }
+        if reply_header & reply_flag::C == reply_flag::C {
+            let _consolidation = reader.read_u8()?;
+        }
+        if reply_header & reply_flag::Z == reply_flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        let body_header = reader.read_u8()?;
+        let body = match body_header & 0x1f {
+            Z_MID_Z_PUT => ResponseBody::Reply(Put::decode(reader, body_header)?),
+            _ => return Err(TransportError::UnexpectMsg),
+        };
+
+        Ok(Response {
+            request_id,
+            keyexpr,
+            body,
+        })
+    }
+}
+
+impl ResponseFinal {
+    pub fn header(&self) -> u8 {
+        Z_MID_N_RESPONSE_FINAL
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Encoding _Z_MID_N_RESPONSE_FINAL");
+
+        writer.write_u8(self.header())?;
+        Varint::<u32>::encode(writer, self.request_id)?;
+
+        Ok(())
+    }
+
+    pub fn decode<R: Reader>(reader: &R, header: u8) -> Result<Self, TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Decoding _Z_MID_N_RESPONSE_FINAL");
+
+        let request_id = Varint::<u32>::decode(reader)?;
+
+        if header & flag::Z == flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        Ok(ResponseFinal { request_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::SliceReader;
+
+    #[test]
+    fn extensions_are_skipped() {
+        // A unit extension on the Response and a Z64 one on the Reply
+        let buf = [
+            Z_MID_N_RESPONSE | flag::N | flag::Z,
+            0x07,
+            0x00,
+            0x01,
+            b'a',
+            0x01,
+            Z_MID_Z_REPLY | reply_flag::Z,
+            0x22,
+            0x05,
+            Z_MID_Z_PUT,
+            0x01,
+            b'x',
+        ];
+        let reader = SliceReader::new(&buf[1..]);
+        assert_eq!(
+            Response::decode(&reader, buf[0]).unwrap(),
+            Response::new(7, KeyExpr::new("a"), b"x")
+        );
+        assert_eq!(reader.remaining(), 0);
+    }
+}
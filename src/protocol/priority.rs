@@ -0,0 +1,16 @@
+/// Priority classes of zenoh, each mapped to its own conduit when QoS has been
+/// negotiated. Lower values are served first.
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Priority {
+    Control = 0,
+    RealTime = 1,
+    InteractiveHigh = 2,
+    InteractiveLow = 3,
+    DataHigh = 4,
+    #[default]
+    Data = 5,
+    DataLow = 6,
+    Background = 7,
+}
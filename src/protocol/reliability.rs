@@ -0,0 +1,17 @@
+/// Delivery guarantee of a Frame, carried by its R flag.
+///
+/// Only reliable frames are checked for sequence number gaps on receive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Reliability {
+    #[default]
+    Reliable,
+    BestEffort,
+}
+
+impl Reliability {
+    #[inline]
+    pub fn is_reliable(self) -> bool {
+        self == Reliability::Reliable
+    }
+}
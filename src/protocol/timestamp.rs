@@ -0,0 +1,55 @@
+//! # Timestamp
+//!
+//! A hybrid logical clock reading, stamped by the source identified by its
+//! ZenohID:
+//!
+//! ```text
+//! ~ time:z64      ~  -- NTP64, seconds in the upper 32 bits
+//! +---------------+
+//! ~ id:<u8;z8>    ~  -- ZenohID of the source
+//! +---------------+
+//! ```
+//!
+//! There is no clock in `no_std`, the time comes from the closure given to
+//! [`crate::ConfigBuilder::clock`].
+
+use crate::{
+    iobuf::{Reader, Writer},
+    protocol::{ProtocolError, Varint, ZenohID},
+    transport::TransportError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp {
+    pub time: u64,
+    pub id: ZenohID,
+}
+
+impl Timestamp {
+    pub fn new(time: u64, id: ZenohID) -> Self {
+        Timestamp { time, id }
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        Varint::<u64>::encode(writer, self.time)?;
+        Varint::<u8>::encode(writer, self.id.size() as u8)?;
+        writer.write_exact(self.id.as_slice())?;
+
+        Ok(())
+    }
+
+    pub fn decode<R: Reader>(reader: &R) -> Result<Self, TransportError> {
+        let time = Varint::<u64>::decode(reader)?;
+
+        let len = Varint::<u8>::decode(reader)? as usize;
+        if len > ZenohID::MAX_SIZE {
+            return Err(ProtocolError::BadZenohIdLength(len).into());
+        }
+        let mut bytes = [0u8; ZenohID::MAX_SIZE];
+        reader.read_exact(&mut bytes[..len])?;
+        let id = ZenohID::try_from(&bytes[..len])?;
+
+        Ok(Timestamp { time, id })
+    }
+}
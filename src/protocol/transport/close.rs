@@ -0,0 +1,166 @@
+//! # Close message
+//!
+//! The CLOSE message is sent in any of the following two cases:
+//!     1) in response to an INIT or OPEN message which are not accepted;
+//!     2) at any time to arbitrarily close the transport with the corresponding zenoh node.
+//!
+//! Flags:
+//! - S: Session Close  if S==1 Session close or S==0 Link close
+//! - X: Reserved
+//! - Z: Extensions     if Z==1 then zenoh extensions will follow.
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|X|S|  CLOSE  |
+//! +-+-+-+---------+
+//! |    Reason     |
+//! +---------------+
+//! ~  [CloseExts]  ~ if Flag(Z)==1
+//! +---------------+
+//!
+
+use crate::{
+    iobuf::{Reader, Writer},
+    transport::TransportError,
+};
+
+use super::{frame, TransportBody, TransportMessage};
+
+pub(crate) const Z_MID_T_CLOSE: u8 = 0x03;
+
+pub mod flag {
+    pub const S: u8 = 1 << 5; // 0x20 Session close if S==1 then the whole session is closed, else only the link
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+pub mod reason {
+    pub const GENERIC: u8 = 0x00;
+    pub const UNSUPPORTED: u8 = 0x01;
+    pub const INVALID: u8 = 0x02;
+    pub const MAX_SESSIONS: u8 = 0x03;
+    pub const MAX_LINKS: u8 = 0x04;
+    pub const EXPIRED: u8 = 0x05;
+    pub const UNRESPONSIVE: u8 = 0x06;
+}
+
+/// The reason codes of [`reason`] as an enum, unknown codes are kept as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CloseReason {
+    Generic,
+    Unsupported,
+    Invalid,
+    MaxSessions,
+    MaxLinks,
+    Expired,
+    Unresponsive,
+    Other(u8),
+}
+
+impl From<u8> for CloseReason {
+    fn from(value: u8) -> Self {
+        match value {
+            reason::GENERIC => CloseReason::Generic,
+            reason::UNSUPPORTED => CloseReason::Unsupported,
+            reason::INVALID => CloseReason::Invalid,
+            reason::MAX_SESSIONS => CloseReason::MaxSessions,
+            reason::MAX_LINKS => CloseReason::MaxLinks,
+            reason::EXPIRED => CloseReason::Expired,
+            reason::UNRESPONSIVE => CloseReason::Unresponsive,
+            other => CloseReason::Other(other),
+        }
+    }
+}
+
+impl From<CloseReason> for u8 {
+    fn from(value: CloseReason) -> Self {
+        match value {
+            CloseReason::Generic => reason::GENERIC,
+            CloseReason::Unsupported => reason::UNSUPPORTED,
+            CloseReason::Invalid => reason::INVALID,
+            CloseReason::MaxSessions => reason::MAX_SESSIONS,
+            CloseReason::MaxLinks => reason::MAX_LINKS,
+            CloseReason::Expired => reason::EXPIRED,
+            CloseReason::Unresponsive => reason::UNRESPONSIVE,
+            CloseReason::Other(other) => other,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Close {
+    pub session: bool,
+    pub reason: u8,
+}
+
+impl Close {
+    pub fn message<'c>(session: bool, reason: u8) -> TransportMessage<'c> {
+        TransportMessage {
+            body: TransportBody::Close(Close { session, reason }),
+        }
+    }
+
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_T_CLOSE;
+
+        if self.session {
+            header |= flag::S;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Encoding _Z_MID_T_CLOSE");
+
+        writer.write_u8(self.header())?;
+        writer.write_u8(self.reason)?;
+
+        Ok(())
+    }
+
+    pub fn decode<'c, R: Reader>(
+        reader: &R,
+        header: u8,
+    ) -> Result<TransportMessage<'c>, TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Decoding _Z_MID_T_CLOSE");
+
+        let session = header & flag::S == flag::S;
+        let reason = reader.read_u8()?;
+
+        if header & flag::Z == flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        Ok(TransportMessage {
+            body: TransportBody::Close(Close { session, reason }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::SliceReader;
+
+    #[test]
+    fn extensions_are_skipped() {
+        // A ZBUF extension followed by a unit one
+        let buf = [
+            Z_MID_T_CLOSE | flag::S | flag::Z,
+            reason::EXPIRED,
+            0xc1,
+            0x01,
+            0xaa,
+            0x02,
+        ];
+        let reader = SliceReader::new(&buf[1..]);
+        assert_eq!(
+            Close::decode(&reader, buf[0]).unwrap(),
+            Close::message(true, reason::EXPIRED)
+        );
+        assert_eq!(reader.remaining(), 0);
+    }
+}
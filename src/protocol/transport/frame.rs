@@ -0,0 +1,274 @@
+//! # Frame message
+//!
+//! The FRAME message is used to transmit one or more complete serialized
+//! [`crate::protocol::network::NetworkMessage`]. I.e., the total length of the
+//! serialized [`crate::protocol::network::NetworkMessage`] (s) MUST be smaller
+//! than the maximum batch size (i.e. 2^16-1) and the link MTU.
+//!
+//! Flags:
+//! - R: Reliable      if R==1 it concerns the reliable channel, else the best-effort channel
+//! - Z: Extensions    if Z==1 then zenoh extensions will follow.
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|X|R|  FRAME  |
+//! +-+-+-+---------+
+//! %    seq num    %
+//! +---------------+
+//! ~  [FrameExts]  ~ if Flag(Z)==1
+//! +---------------+
+//! ~  [NetworkMsg] ~
+//! +---------------+
+//!
+//! The only extension understood is QoS (id 0x1, Z64, mandatory) whose value
+//! carries the priority the frame's conduit maps to:
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|0_1|M|  0x1  |
+//! +-+-+-+-+-------+
+//! %  qos:z64      %  -- bits 0-2: priority
+//! +---------------+
+//!
+//! The network messages are not part of [`Frame`] itself: they are written right
+//! after the frame header and read back from the same reader once the frame has
+//! been decoded, see [`Frame::messages`].
+//!
+
+use crate::{
+    iobuf::{Reader, Writer},
+    protocol::{network::NetworkMessage, Varint},
+    transport::TransportError,
+};
+
+use super::{TransportBody, TransportMessage};
+
+pub(crate) const Z_MID_T_FRAME: u8 = 0x05;
+
+pub mod flag {
+    pub const R: u8 = 1 << 5; // 0x20 Reliable      if R==1 then the frame is reliable
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+pub mod ext {
+    pub const QOS: u8 = 0x31; // Z64 | M | 0x1
+    pub const PRIORITY_MASK: u8 = 0x07;
+    pub const ID_MASK: u8 = 0x0f;
+    pub const ENC_MASK: u8 = 0x60;
+    pub const ENC_UNIT: u8 = 0x00;
+    pub const ENC_Z64: u8 = 0x20;
+    pub const ENC_ZBUF: u8 = 0x40;
+    pub const M: u8 = 1 << 4; // 0x10 Mandatory
+    pub const Z: u8 = 1 << 7; // 0x80 More          if Z==1 then another extension will follow
+
+    use crate::{iobuf::Reader, protocol::Varint, transport::TransportError};
+
+    /// Skips the body of the extension with header `eh`, refusing it if the
+    /// peer marked it mandatory since we do not understand it.
+    pub(crate) fn skip<R: Reader>(reader: &R, eh: u8) -> Result<(), TransportError> {
+        if eh & M == M {
+            return Err(TransportError::UnknownMandatoryExtension(eh & ID_MASK));
+        }
+
+        match eh & ENC_MASK {
+            ENC_UNIT => {}
+            ENC_Z64 => {
+                Varint::<u64>::decode(reader)?;
+            }
+            ENC_ZBUF => {
+                let len = Varint::<u32>::decode(reader)? as usize;
+                reader.skip(len)?;
+            }
+            _ => return Err(TransportError::ReservedExtensionEncoding(eh & ID_MASK)),
+        }
+
+        Ok(())
+    }
+
+    /// Skips the whole extension chain of a message sent with its Z flag,
+    /// for messages that understand none of their extensions.
+    pub(crate) fn skip_all<R: Reader>(reader: &R) -> Result<(), TransportError> {
+        loop {
+            let eh = reader.read_u8()?;
+            skip(reader, eh)?;
+            if eh & Z == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub reliable: bool,
+    pub sn: u64,
+    /// Priority of the conduit the frame belongs to, only sent on QoS transports.
+    pub qos: Option<u8>,
+}
+
+impl Frame {
+    pub fn message<'c>(reliable: bool, sn: u64) -> TransportMessage<'c> {
+        Self::message_with_qos(reliable, sn, None)
+    }
+
+    pub fn message_with_qos<'c>(reliable: bool, sn: u64, qos: Option<u8>) -> TransportMessage<'c> {
+        TransportMessage {
+            body: TransportBody::Frame(Frame { reliable, sn, qos }),
+        }
+    }
+
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_T_FRAME;
+
+        if self.reliable {
+            header |= flag::R;
+        }
+        if self.qos.is_some() {
+            header |= flag::Z;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Encoding _Z_MID_T_FRAME");
+
+        writer.write_u8(self.header())?;
+
+        Varint::<u64>::encode(writer, self.sn)?;
+
+        if let Some(qos) = self.qos {
+            writer.write_u8(ext::QOS)?;
+            Varint::<u64>::encode(writer, (qos & ext::PRIORITY_MASK) as u64)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn decode<'c, R: Reader>(
+        reader: &R,
+        header: u8,
+    ) -> Result<TransportMessage<'c>, TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::trace!("Decoding _Z_MID_T_FRAME");
+
+        let reliable = header & flag::R == flag::R;
+        let sn = Varint::<u64>::decode(reader)?;
+
+        let mut qos = None;
+        let mut more = header & flag::Z == flag::Z;
+        while more {
+            let eh = reader.read_u8()?;
+            more = eh & ext::Z == ext::Z;
+
+            match eh & !ext::Z {
+                ext::QOS => qos = Some(Varint::<u64>::decode(reader)? as u8 & ext::PRIORITY_MASK),
+                _ => ext::skip(reader, eh)?,
+            }
+        }
+
+        Ok(TransportMessage {
+            body: TransportBody::Frame(Frame { reliable, sn, qos }),
+        })
+    }
+
+    /// Decodes the network messages left in `reader` after the frame header,
+    /// each one starts where the previous ended. Iteration stops after the
+    /// first error.
+    pub fn messages<'a, R: Reader>(
+        reader: &'a R,
+    ) -> impl Iterator<Item = Result<NetworkMessage<'a>, TransportError>> + 'a {
+        let mut failed = false;
+        core::iter::from_fn(move || {
+            if failed || reader.remaining() == 0 {
+                return None;
+            }
+
+            let msg = NetworkMessage::decode(reader);
+            failed = msg.is_err();
+            Some(msg)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{SliceReader, SliceWriter};
+    use crate::protocol::{
+        keyexpr::KeyExpr,
+        network::push::{Push, PushBody},
+    };
+
+    #[test]
+    fn sn_above_u32_round_trips() {
+        // Within the 64-bit resolution mask
+        let sn = u32::MAX as u64 + 0x1234;
+        let mut buf = [0u8; 16];
+        let mut writer = SliceWriter::new(&mut buf);
+        Frame::message(true, sn).encode(&mut writer).unwrap();
+        let len = writer.len();
+
+        let reader = SliceReader::new(&buf[..len]);
+        let TransportBody::Frame(frame) = TransportMessage::decode(&reader).unwrap().body else {
+            panic!("expected a Frame");
+        };
+        assert_eq!(frame.sn, sn);
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn unknown_extensions_are_skipped_unless_mandatory() {
+        // A Z64 extension with id 0x5, then a ZBUF one with id 0x6
+        let buf = [
+            ext::Z | ext::ENC_Z64 | 0x05,
+            0x81,
+            0x01,
+            ext::ENC_ZBUF | 0x06,
+            0x02,
+            0xaa,
+            0xbb,
+        ];
+        let reader = SliceReader::new(&buf);
+        ext::skip_all(&reader).unwrap();
+        assert_eq!(reader.remaining(), 0);
+
+        let reader = SliceReader::new(&[ext::M | ext::ENC_UNIT | 0x05]);
+        assert!(matches!(
+            ext::skip_all(&reader),
+            Err(TransportError::UnknownMandatoryExtension(0x05))
+        ));
+
+        let reader = SliceReader::new(&[0x60 | 0x05]);
+        assert!(matches!(
+            ext::skip_all(&reader),
+            Err(TransportError::ReservedExtensionEncoding(0x05))
+        ));
+    }
+
+    #[test]
+    fn frame_yields_every_embedded_message() {
+        let mut buf = [0u8; 64];
+        let mut writer = SliceWriter::new(&mut buf);
+        Frame::message(true, 7).encode(&mut writer).unwrap();
+        for (ke, payload) in [("demo/a", b"1"), ("demo/b", b"2")] {
+            NetworkMessage::Push(Push::new(KeyExpr::try_from(ke).unwrap(), payload))
+                .encode(&mut writer)
+                .unwrap();
+        }
+        let len = writer.len();
+
+        let reader = SliceReader::new(&buf[..len]);
+        TransportMessage::decode(&reader).unwrap();
+        let msgs: std::vec::Vec<_> = Frame::messages(&reader).map(Result::unwrap).collect();
+        assert_eq!(msgs.len(), 2);
+        for (msg, payload) in msgs.iter().zip([b"1", b"2"]) {
+            let NetworkMessage::Push(push) = msg else {
+                panic!("expected a Push");
+            };
+            let PushBody::Put(put) = &push.body;
+            assert_eq!(put.payload, payload);
+        }
+    }
+}
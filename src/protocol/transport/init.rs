@@ -36,6 +36,11 @@
 //! ~   [InitExts]  ~ -- if Flag(Z)==1
 //! +---------------+
 //!
+//! The extensions understood are QoS (id 0x1, unit): sent in the InitSyn to
+//! ask for a QoS transport, kept in the InitAck when the peer agrees; and
+//! Patch (id 0x7, z64): the protocol patch level of the sender, left out
+//! when 0.
+//!
 //! If A==1 and S==0 then size parameters are (ie. S flag) are accepted.
 //!
 //! (*) WhatAmI. It indicates the role of the zenoh node sending the INIT
@@ -72,11 +77,13 @@ use crate::{
     iobuf::{Reader, Writer},
     protocol::{whatami::WhatAmI, Varint, ZenohID},
     transport::TransportError,
-    Z_BATCH_UNICAST_SIZE, Z_PROTO_VERSION, Z_REQ_RESOLUTION, Z_SN_RESOLUTION,
+    Z_BATCH_UNICAST_SIZE, Z_MAX_COOKIE_SIZE, Z_PATCH, Z_PROTO_VERSION, Z_REQ_RESOLUTION,
+    Z_SN_RESOLUTION,
 };
 
 use super::{
-    TransportBody, TransportMessage, Z_DEFAULT_MULTICAST_BATCH_SIZE, Z_DEFAULT_RESOLUTION_SIZE,
+    frame, TransportBody, TransportMessage, Z_DEFAULT_MULTICAST_BATCH_SIZE,
+    Z_DEFAULT_RESOLUTION_SIZE,
 };
 
 pub(crate) const Z_MID_T_INIT: u8 = 0x01;
@@ -87,6 +94,11 @@ pub mod flag {
     pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
 }
 
+pub mod ext {
+    pub const QOS: u8 = 0x01; // Unit | 0x1
+    pub const PATCH: u8 = 0x27; // Z64 | 0x7
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct InitSyn<'c> {
     pub zid: ZenohID,
@@ -95,11 +107,16 @@ pub struct InitSyn<'c> {
     whatami: WhatAmI,
     pub req_id_res: u8,
     pub seq_num_res: u8,
+    /// Whether the QoS extension is present.
+    pub qos: bool,
+    /// Protocol patch level, 0 when the Patch extension is absent.
+    pub patch: u8,
     version: u8,
+    size_params: bool,
 }
 
 impl<'c> InitSyn<'c> {
-    pub fn new(whatami: WhatAmI, zid: ZenohID) -> TransportMessage<'c> {
+    pub fn message(whatami: WhatAmI, zid: ZenohID) -> TransportMessage<'c> {
         TransportMessage {
             body: TransportBody::InitSyn(InitSyn {
                 version: Z_PROTO_VERSION,
@@ -109,10 +126,49 @@ impl<'c> InitSyn<'c> {
                 req_id_res: Z_REQ_RESOLUTION,
                 seq_num_res: Z_SN_RESOLUTION,
                 batch_size: Z_BATCH_UNICAST_SIZE,
+                qos: false,
+                patch: Z_PATCH,
+                size_params: true,
             }),
         }
     }
 
+    /// An InitAck answering with `cookie`, as sent by the accepting side.
+    pub fn ack(whatami: WhatAmI, zid: ZenohID, cookie: &'c [u8]) -> TransportMessage<'c> {
+        TransportMessage {
+            body: TransportBody::InitAck(InitSyn {
+                version: Z_PROTO_VERSION,
+                whatami,
+                zid,
+                cookie: Some(cookie),
+                req_id_res: Z_REQ_RESOLUTION,
+                seq_num_res: Z_SN_RESOLUTION,
+                batch_size: Z_BATCH_UNICAST_SIZE,
+                qos: false,
+                patch: Z_PATCH,
+                size_params: true,
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn whatami(&self) -> WhatAmI {
+        self.whatami
+    }
+
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Whether the size parameters were given. A decoded InitAck without the
+    /// S flag accepts the ones proposed in the InitSyn, its own fields then
+    /// only hold the defaults.
+    #[inline]
+    pub fn has_size_params(&self) -> bool {
+        self.size_params
+    }
+
     pub fn header(&self) -> u8 {
         let mut header = Z_MID_T_INIT;
 
@@ -122,15 +178,30 @@ impl<'c> InitSyn<'c> {
         {
             header |= flag::S;
         }
+        if self.qos || self.patch != 0 {
+            header |= flag::Z;
+        }
 
         header
     }
 
     pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        self.encode_with(writer, false)
+    }
+
+    /// Encodes the message as an InitAck, with the A flag and its cookie.
+    pub fn encode_ack<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        self.encode_with(writer, true)
+    }
+
+    fn encode_with<W: Writer>(&self, writer: &mut W, ack: bool) -> Result<(), TransportError> {
         #[cfg(feature = "defmt")]
         defmt::debug!("Encoding _Z_MID_T_INIT");
 
-        let header = self.header();
+        let mut header = self.header();
+        if ack {
+            header |= flag::A;
+        }
 
         writer.write_u8(header)?;
 
@@ -144,30 +215,38 @@ impl<'c> InitSyn<'c> {
         let flags = ((self.zid.size() as u8 - 1) << 4) | whatami;
         writer.write_u8(flags)?;
 
-        let zid = self.zid.to_le_bytes();
-        writer.write_exact(&zid[..self.zid.size()])?;
+        writer.write_exact(self.zid.as_slice())?;
 
         if header & flag::S == flag::S {
             let mut cbyte = 0u8;
             cbyte |= self.seq_num_res & 0x03;
             cbyte |= (self.req_id_res & 0x03) << 2;
             writer.write_u8(cbyte)?;
-            writer.write_exact(&self.batch_size.to_le_bytes())?;
+            writer.write_u16_le(self.batch_size)?;
         }
 
         if header & flag::A == flag::A {
-            if let Some(cookie) = self.cookie {
-                writer.write_exact(cookie)?;
-            }
+            let cookie = self.cookie.unwrap_or_default();
+            Varint::<u64>::encode(writer, cookie.len() as u64)?;
+            writer.write_exact(cookie)?;
+        }
+
+        if self.qos {
+            let more = if self.patch != 0 { frame::ext::Z } else { 0 };
+            writer.write_u8(ext::QOS | more)?;
+        }
+        if self.patch != 0 {
+            writer.write_u8(ext::PATCH)?;
+            Varint::<u64>::encode(writer, self.patch as u64)?;
         }
 
         Ok(())
     }
 
     pub fn decode<R: Reader>(
-        reader: &mut R,
+        reader: &'c R,
         header: u8,
-    ) -> Result<TransportMessage, TransportError> {
+    ) -> Result<TransportMessage<'c>, TransportError> {
         #[cfg(feature = "defmt")]
         defmt::debug!("Decoding _Z_MID_T_INIT");
 
@@ -175,22 +254,18 @@ impl<'c> InitSyn<'c> {
 
         let cbyte = reader.read_u8()?;
 
-        let whatami = WhatAmI::from(cbyte);
+        let whatami = WhatAmI::try_from(cbyte)?;
         let zid_len = (((cbyte & 0xF0) >> 4) + 1) as usize;
 
         let mut zid_bytes = [0u8; 16];
         reader.read_exact(&mut zid_bytes[0..zid_len])?;
-
-        let zid = u128::from_le_bytes(zid_bytes);
-        let zid = ZenohID::from(zid);
+        let zid = ZenohID::try_from(&zid_bytes[0..zid_len])?;
 
         let (seq_num_res, req_id_res, batch_size) = if header & flag::S == flag::S {
             let cbyte = reader.read_u8()?;
             let seq_num_res = cbyte & 0x03;
             let req_id_res = (cbyte & 0x0C) >> 2;
-            let mut batch_size_bytes = [0u8; 2];
-            reader.read_exact(&mut batch_size_bytes)?;
-            let batch_size = u16::from_le_bytes(batch_size_bytes);
+            let batch_size = reader.read_u16_le()?;
 
             (seq_num_res, req_id_res, batch_size)
         } else {
@@ -201,8 +276,15 @@ impl<'c> InitSyn<'c> {
             )
         };
 
-        let cookie = if header & flag::A == flag::A {
+        let cookie = if header & flag::A == flag::A && reader.remaining() == 0 {
+            // A minimal peer may set A without appending any cookie bytes,
+            // treat it as an empty cookie instead of misparsing the length.
+            Some(&[][..])
+        } else if header & flag::A == flag::A {
             let cookie_len = Varint::<u64>::decode(reader)? as usize;
+            if cookie_len > Z_MAX_COOKIE_SIZE {
+                return Err(TransportError::CookieTooLarge(cookie_len));
+            }
 
             let cookie = reader.read_slice_in_place(cookie_len)?;
 
@@ -214,8 +296,22 @@ impl<'c> InitSyn<'c> {
             None
         };
 
-        if header & flag::Z == flag::Z {
-            unimplemented!()
+        let mut qos = false;
+        let mut patch = 0;
+        let mut more = header & flag::Z == flag::Z;
+        while more {
+            let eh = reader.read_u8()?;
+            more = eh & frame::ext::Z == frame::ext::Z;
+
+            match eh & !frame::ext::Z {
+                ext::QOS => qos = true,
+                ext::PATCH => {
+                    // Too high to be one we know of either way
+                    let level = Varint::<u64>::decode(reader)?;
+                    patch = u8::try_from(level).unwrap_or(u8::MAX);
+                }
+                _ => frame::ext::skip(reader, eh)?,
+            }
         }
 
         if header & flag::A == flag::A {
@@ -227,7 +323,10 @@ impl<'c> InitSyn<'c> {
                     whatami,
                     req_id_res,
                     seq_num_res,
+                    qos,
+                    patch,
                     version,
+                    size_params: header & flag::S == flag::S,
                 }),
             })
         } else {
@@ -239,9 +338,183 @@ impl<'c> InitSyn<'c> {
                     whatami,
                     req_id_res,
                     seq_num_res,
+                    qos,
+                    patch,
                     version,
+                    size_params: header & flag::S == flag::S,
                 }),
             })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{SliceReader, SliceWriter, Writer, ZVec};
+    use crate::protocol::ProtocolError;
+
+    /// Client InitSyn from ZID 0xbeef with the default resolutions and a
+    /// 2048 byte batch.
+    const INIT_SYN: &[u8] = &[
+        0x41, // |Z=0|S=1|A=0| INIT
+        0x09, // version
+        0x12, // zid_len=1 (2 bytes) | whatami Client
+        0xef, 0xbe, // zid
+        0x0a, // kid=0 | rid=32 bits | fsn=32 bits
+        0x00, 0x08, // batch size
+    ];
+
+    /// Router InitAck from ZID 0x49 carrying the cookie [0xc0, 0xff].
+    const INIT_ACK: &[u8] = &[
+        0x61, // |Z=0|S=1|A=1| INIT
+        0x09, // version
+        0x00, // zid_len=0 (1 byte) | whatami Router
+        0x49, // zid
+        0x0a, // kid=0 | rid=32 bits | fsn=32 bits
+        0x00, 0x08, // batch size
+        0x02, // cookie length
+        0xc0, 0xff, // cookie
+    ];
+
+    #[test]
+    fn init_syn_wire_layout() {
+        let syn = InitSyn::message(WhatAmI::Client, ZenohID::from(0xbeef));
+
+        let mut buf = ZVec::new();
+        let len = syn.encode(&mut buf).unwrap();
+        assert_eq!(len, buf.len());
+        assert_eq!(buf.as_slice(), INIT_SYN);
+
+        let reader = SliceReader::new(INIT_SYN);
+        assert_eq!(TransportMessage::decode(&reader).unwrap(), syn);
+    }
+
+    #[test]
+    fn init_syn_round_trips_through_a_stack_array() {
+        let syn = InitSyn::message(WhatAmI::Client, ZenohID::from(0xbeef));
+
+        let mut buf = [0u8; 32];
+        let mut writer = SliceWriter::new(&mut buf);
+        syn.encode(&mut writer).unwrap();
+        let len = writer.len();
+        assert_eq!(&buf[..len], INIT_SYN);
+
+        let reader = SliceReader::new(&buf[..len]);
+        assert_eq!(TransportMessage::decode(&reader).unwrap(), syn);
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn init_ack_wire_layout() {
+        let ack = InitSyn::ack(WhatAmI::Router, ZenohID::from(0x49), &[0xc0, 0xff]);
+
+        let mut buf = ZVec::new();
+        ack.encode(&mut buf).unwrap();
+        assert_eq!(buf.as_slice(), INIT_ACK);
+
+        let reader = SliceReader::new(INIT_ACK);
+        assert_eq!(TransportMessage::decode(&reader).unwrap(), ack);
+    }
+
+    #[test]
+    fn init_ack_without_cookie_bytes_has_an_empty_cookie() {
+        // INIT_ACK cut right after the batch size
+        let reader = SliceReader::new(&INIT_ACK[1..7]);
+        let TransportBody::InitAck(iam) = InitSyn::decode(&reader, INIT_ACK[0]).unwrap().body
+        else {
+            panic!("expected an InitAck");
+        };
+        assert_eq!(iam.cookie, Some(&[][..]));
+    }
+
+    #[test]
+    fn init_ack_cookie_borrows_the_receive_buffer() {
+        let mut buf = ZVec::new();
+        buf.write_exact(INIT_ACK).unwrap();
+        let s = buf.extract_slice(INIT_ACK.len()).unwrap();
+
+        let TransportBody::InitAck(iam) = TransportMessage::decode(&s).unwrap().body else {
+            panic!("expected an InitAck");
+        };
+        let cookie = iam.cookie.unwrap();
+        assert_eq!(cookie, &[0xc0, 0xff]);
+        assert_eq!(
+            cookie.as_ptr_range(),
+            s.as_ref()[INIT_ACK.len() - 2..].as_ptr_range()
+        );
+    }
+
+    #[test]
+    fn zid_keeps_trailing_zero_bytes() {
+        let mut raw = [0u8; ZenohID::MAX_SIZE];
+        raw[0] = 0x49;
+        let zid = ZenohID::try_from(&raw[..]).unwrap();
+
+        let mut buf = [0u8; 64];
+        let mut writer = SliceWriter::new(&mut buf);
+        InitSyn::message(WhatAmI::Client, zid)
+            .encode(&mut writer)
+            .unwrap();
+        let len = writer.len();
+
+        let reader = SliceReader::new(&buf[..len]);
+        let TransportBody::InitSyn(syn) = TransportMessage::decode(&reader).unwrap().body else {
+            panic!("expected an InitSyn");
+        };
+        assert_eq!(syn.zid.size(), ZenohID::MAX_SIZE);
+        assert_eq!(syn.zid, zid);
+        assert_ne!(syn.zid, ZenohID::from(0x49));
+    }
+
+    #[test]
+    fn patch_level_round_trips() {
+        let mut buf = [0u8; 64];
+        let mut writer = SliceWriter::new(&mut buf);
+        let mut syn = InitSyn::message(WhatAmI::Client, ZenohID::from(0x49));
+        if let TransportBody::InitSyn(ism) = &mut syn.body {
+            ism.qos = true;
+            ism.patch = 1;
+        }
+        syn.encode(&mut writer).unwrap();
+        let len = writer.len();
+
+        let reader = SliceReader::new(&buf[..len]);
+        assert_eq!(TransportMessage::decode(&reader).unwrap(), syn);
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn reserved_whatami_is_a_protocol_error() {
+        let reader = SliceReader::new(&[Z_MID_T_INIT, Z_PROTO_VERSION, 0b11]);
+        assert!(matches!(
+            TransportMessage::decode(&reader),
+            Err(TransportError::ProtocolError(ProtocolError::BadWhatAmI(
+                0b11
+            )))
+        ));
+    }
+
+    #[test]
+    fn init_ack_without_s_flag_has_no_size_params() {
+        let mut buf = [0u8; 64];
+        let mut writer = SliceWriter::new(&mut buf);
+        let mut ack = InitSyn::ack(WhatAmI::Router, ZenohID::from(0xbeef), &[0xc0]);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            // The defaults are left out of the wire
+            iam.seq_num_res = Z_DEFAULT_RESOLUTION_SIZE;
+            iam.req_id_res = Z_DEFAULT_RESOLUTION_SIZE;
+            iam.batch_size = Z_DEFAULT_MULTICAST_BATCH_SIZE;
+        }
+        ack.encode(&mut writer).unwrap();
+        let len = writer.len();
+        assert_eq!(buf[0] & flag::S, 0);
+
+        let reader = SliceReader::new(&buf[..len]);
+        let TransportBody::InitAck(iam) = TransportMessage::decode(&reader).unwrap().body else {
+            panic!("expected an InitAck");
+        };
+        assert!(!iam.has_size_params());
+        assert_eq!(iam.cookie, Some(&[0xc0][..]));
+    }
+}
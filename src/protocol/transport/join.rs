@@ -0,0 +1,253 @@
+//! # Join message
+//!
+//! The JOIN message is sent on a multicast Locator to advertise the transport parameters.
+//!
+//! Flags:
+//! - T: Lease period  if T==1 then the lease period is in seconds else in milliseconds
+//! - S: Size params   if S==1 then size parameters are exchanged
+//! - Z: Extensions    if Z==1 then zenoh extensions will follow.
+//!
+//!  7 6 5 4 3 2 1 0
+//! +-+-+-+-+-+-+-+-+
+//! |Z|S|T|   JOIN  |
+//! +-+-+-+---------+
+//! |    version    |
+//! +---------------+
+//! |zid_len|x|x|wai| (#)(*)
+//! +-------+-+-+---+
+//! ~      [u8]     ~ -- ZenohID of the sender of the JOIN message
+//! +---------------+
+//! |x|x|kid|rid|fsn| \                -- SN/ID resolution (+)
+//! +---------------+  | if Flag(S)==1
+//! |      u16      |  |               -- Batch Size ($)
+//! |               | /
+//! +---------------+
+//! %     lease     % -- Lease period of the sender of the JOIN message
+//! +---------------+
+//! %  next_sn_re   % -- Next SN to be sent on the reliable channel
+//! +---------------+
+//! %  next_sn_be   % -- Next SN to be sent on the best-effort channel
+//! +---------------+
+//! ~  [JoinExts]   ~ -- if Flag(Z)==1
+//! +---------------+
+//!
+//! (*), (#), (+) and ($) have the same meaning as in the [`super::init`] message.
+//!
+
+use crate::{
+    iobuf::{Reader, Writer},
+    protocol::{transport::frame, whatami::WhatAmI, Varint, ZenohID},
+    transport::TransportError,
+    Z_PROTO_VERSION, Z_REQ_RESOLUTION, Z_SN_RESOLUTION,
+};
+
+use super::{
+    TransportBody, TransportMessage, Z_DEFAULT_MULTICAST_BATCH_SIZE, Z_DEFAULT_RESOLUTION_SIZE,
+};
+
+pub(crate) const Z_MID_T_JOIN: u8 = 0x00;
+
+pub mod flag {
+    pub const T: u8 = 1 << 5; // 0x20 Lease period  if T==1 then the lease period is in seconds else in milliseconds
+    pub const S: u8 = 1 << 6; // 0x40 Size params   if S==1 then size parameters are exchanged
+    pub const Z: u8 = 1 << 7; // 0x80 Extensions    if Z==1 then an extension will follow
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Join {
+    pub zid: ZenohID,
+    pub whatami: WhatAmI,
+    pub batch_size: u16,
+    pub req_id_res: u8,
+    pub seq_num_res: u8,
+    pub lease: u32,
+    pub next_sn_reliable: u64,
+    pub next_sn_best_effort: u64,
+    version: u8,
+}
+
+impl Join {
+    pub fn message<'c>(
+        whatami: WhatAmI,
+        zid: ZenohID,
+        batch_size: u16,
+        lease: u32,
+        next_sn: u64,
+    ) -> TransportMessage<'c> {
+        TransportMessage {
+            body: TransportBody::Join(Join {
+                version: Z_PROTO_VERSION,
+                whatami,
+                zid,
+                batch_size,
+                req_id_res: Z_REQ_RESOLUTION,
+                seq_num_res: Z_SN_RESOLUTION,
+                lease,
+                next_sn_reliable: next_sn,
+                next_sn_best_effort: next_sn,
+            }),
+        }
+    }
+
+    pub fn header(&self) -> u8 {
+        let mut header = Z_MID_T_JOIN;
+
+        if self.lease.is_multiple_of(1000) {
+            header |= flag::T;
+        }
+
+        if self.batch_size != Z_DEFAULT_MULTICAST_BATCH_SIZE
+            || self.seq_num_res != Z_DEFAULT_RESOLUTION_SIZE
+            || self.req_id_res != Z_DEFAULT_RESOLUTION_SIZE
+        {
+            header |= flag::S;
+        }
+
+        header
+    }
+
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Encoding _Z_MID_T_JOIN");
+
+        let header = self.header();
+
+        writer.write_u8(header)?;
+
+        writer.write_u8(self.version)?;
+
+        let flags = ((self.zid.size() as u8 - 1) << 4) | self.whatami as u8;
+        writer.write_u8(flags)?;
+
+        writer.write_exact(self.zid.as_slice())?;
+
+        if header & flag::S == flag::S {
+            let mut cbyte = 0u8;
+            cbyte |= self.seq_num_res & 0x03;
+            cbyte |= (self.req_id_res & 0x03) << 2;
+            writer.write_u8(cbyte)?;
+            writer.write_u16_le(self.batch_size)?;
+        }
+
+        if header & flag::T == flag::T {
+            Varint::<u64>::encode(writer, self.lease as u64 / 1000)?;
+        } else {
+            Varint::<u64>::encode(writer, self.lease as u64)?;
+        }
+
+        Varint::<u64>::encode(writer, self.next_sn_reliable)?;
+        Varint::<u64>::encode(writer, self.next_sn_best_effort)?;
+
+        Ok(())
+    }
+
+    pub fn decode<'c, R: Reader>(
+        reader: &R,
+        header: u8,
+    ) -> Result<TransportMessage<'c>, TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Decoding _Z_MID_T_JOIN");
+
+        let version = reader.read_u8()?;
+
+        let cbyte = reader.read_u8()?;
+
+        let whatami = WhatAmI::try_from(cbyte)?;
+        let zid_len = (((cbyte & 0xF0) >> 4) + 1) as usize;
+
+        let mut zid_bytes = [0u8; 16];
+        reader.read_exact(&mut zid_bytes[0..zid_len])?;
+        let zid = ZenohID::try_from(&zid_bytes[0..zid_len])?;
+
+        let (seq_num_res, req_id_res, batch_size) = if header & flag::S == flag::S {
+            let cbyte = reader.read_u8()?;
+            let seq_num_res = cbyte & 0x03;
+            let req_id_res = (cbyte & 0x0C) >> 2;
+            let batch_size = reader.read_u16_le()?;
+
+            (seq_num_res, req_id_res, batch_size)
+        } else {
+            (
+                Z_DEFAULT_RESOLUTION_SIZE,
+                Z_DEFAULT_RESOLUTION_SIZE,
+                Z_DEFAULT_MULTICAST_BATCH_SIZE,
+            )
+        };
+
+        let lease = Varint::<u32>::decode(reader)?;
+        let lease = if header & flag::T == flag::T {
+            lease
+                .checked_mul(1000)
+                .ok_or(TransportError::InvalidLease)?
+        } else {
+            lease
+        };
+
+        let next_sn_reliable = Varint::<u64>::decode(reader)?;
+        let next_sn_best_effort = Varint::<u64>::decode(reader)?;
+
+        if header & flag::Z == flag::Z {
+            frame::ext::skip_all(reader)?;
+        }
+
+        Ok(TransportMessage {
+            body: TransportBody::Join(Join {
+                zid,
+                whatami,
+                batch_size,
+                req_id_res,
+                seq_num_res,
+                lease,
+                next_sn_reliable,
+                next_sn_best_effort,
+                version,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{SliceReader, SliceWriter};
+
+    fn encode(lease: u32, buf: &mut [u8]) -> usize {
+        let mut writer = SliceWriter::new(buf);
+        Join::message(WhatAmI::Peer, ZenohID::from(0x49), 8192, lease, 0)
+            .encode(&mut writer)
+            .unwrap();
+        writer.len()
+    }
+
+    #[test]
+    fn extensions_are_skipped() {
+        let mut buf = [0u8; 32];
+        let len = encode(10000, &mut buf);
+        // A ZBUF extension followed by a Z64 one
+        buf[0] |= flag::Z;
+        buf[len..len + 6].copy_from_slice(&[0xc2, 0x02, 0xaa, 0xbb, 0x23, 0x07]);
+
+        let reader = SliceReader::new(&buf[1..len + 6]);
+        let TransportBody::Join(join) = Join::decode(&reader, buf[0]).unwrap().body else {
+            panic!("expected a Join");
+        };
+        assert_eq!(join.lease, 10000);
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn lease_overflowing_in_milliseconds_is_rejected() {
+        let mut buf = [0u8; 32];
+        let len = encode(1000, &mut buf);
+        // header, version, whatami and the ZID, then a lease of 1 s
+        assert_eq!(&buf[4..len], &[0x01, 0x00, 0x00]);
+
+        // u32::MAX seconds instead
+        buf[4..10].copy_from_slice(&[0xff, 0xff, 0xff, 0xff, 0x0f, 0x00]);
+        let reader = SliceReader::new(&buf[1..11]);
+        assert!(matches!(
+            Join::decode(&reader, buf[0]),
+            Err(TransportError::InvalidLease)
+        ));
+    }
+}
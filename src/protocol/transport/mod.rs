@@ -1,14 +1,18 @@
-// use heapless::{arc_pool, box_pool, pool::boxed::Box};
+use close::{Close, Z_MID_T_CLOSE};
+use frame::{Frame, Z_MID_T_FRAME};
 use init::{InitSyn, Z_MID_T_INIT};
+use join::{Join, Z_MID_T_JOIN};
 use open::{OpenSyn, Z_MID_T_OPEN};
-// use once_cell::unsync::Lazy;
 
 use crate::{
-    iobuf::{Reader, Writer},
+    iobuf::{ChunkWriter, Reader, Writer},
     transport::TransportError,
 };
 
+pub mod close;
+pub mod frame;
 pub mod init;
+pub mod join;
 pub mod open;
 
 const Z_DEFAULT_MULTICAST_BATCH_SIZE: u16 = 8192;
@@ -17,96 +21,246 @@ const Z_DEFAULT_RESOLUTION_SIZE: u8 = 2;
 // Zenoh messages at zenoh-transport level
 #[derive(Debug, PartialEq, Eq)]
 pub enum TransportBody<'c> {
-    Join,
+    Join(Join),
     InitSyn(InitSyn<'c>),
     InitAck(InitSyn<'c>),
     OpenSyn(OpenSyn<'c>),
     OpenAck(OpenSyn<'c>),
-    Close,
+    Close(Close),
     KeepAlive,
-    Frame,
+    Frame(Frame),
     Fragment,
 }
 
+impl TransportBody<'_> {
+    /// Name of the message, for traces that do not need the whole body.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TransportBody::Join(_) => "Join",
+            TransportBody::InitSyn(_) => "InitSyn",
+            TransportBody::InitAck(_) => "InitAck",
+            TransportBody::OpenSyn(_) => "OpenSyn",
+            TransportBody::OpenAck(_) => "OpenAck",
+            TransportBody::Close(_) => "Close",
+            TransportBody::KeepAlive => "KeepAlive",
+            TransportBody::Frame(_) => "Frame",
+            TransportBody::Fragment => "Fragment",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct TransportMessage<'c> {
     pub body: TransportBody<'c>,
 }
 
 impl<'c> TransportMessage<'c> {
-    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+    /// Encodes the message into `writer` and returns the number of bytes produced.
+    pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<usize, TransportError> {
+        let start = writer.written();
+
         match &self.body {
+            TransportBody::Join(b) => {
+                b.encode(writer)?;
+            }
             TransportBody::InitSyn(b) => {
                 b.encode(writer)?;
             }
+            TransportBody::InitAck(b) => {
+                b.encode_ack(writer)?;
+            }
             TransportBody::OpenSyn(b) => {
                 b.encode(writer)?;
             }
-            _ => todo!(),
+            TransportBody::OpenAck(b) => {
+                b.encode_ack(writer)?;
+            }
+            TransportBody::Close(b) => {
+                b.encode(writer)?;
+            }
+            TransportBody::Frame(b) => {
+                b.encode(writer)?;
+            }
+            _ => return Err(TransportError::UnsupportedMessage),
         }
 
-        Ok(())
+        Ok(writer.written() - start)
+    }
+
+    /// Encodes the message without an intermediate buffer, `on_chunk` is called
+    /// with each piece of the encoding in order.
+    pub fn encode_chunks<F: FnMut(&[u8])>(&self, on_chunk: F) -> Result<usize, TransportError> {
+        self.encode(&mut ChunkWriter::new(on_chunk))
     }
 
-    pub fn decode<'r: 'c, R: Reader>(reader: &'r mut R) -> Result<Self, TransportError> {
+    pub fn decode<R: Reader>(reader: &'c R) -> Result<Self, TransportError> {
         let header = reader.read_u8()?;
 
         match header & 0x1f {
+            Z_MID_T_JOIN => join::Join::decode(reader, header),
             Z_MID_T_INIT => init::InitSyn::decode(reader, header),
             Z_MID_T_OPEN => open::OpenSyn::decode(reader, header),
-            _ => {
-                unimplemented!("Unknown message type: {}", header);
-            }
+            Z_MID_T_CLOSE => close::Close::decode(reader, header),
+            Z_MID_T_FRAME => frame::Frame::decode(reader, header),
+            _ => Err(TransportError::UnknownMessageId(header)),
         }
     }
+
+    /// Decodes the messages concatenated in `reader` one after the other until
+    /// it is exhausted, iteration stops after the first error. A Frame must be
+    /// last as the network messages following it are not consumed here.
+    pub fn decode_iter<R: Reader>(
+        reader: &'c R,
+    ) -> impl Iterator<Item = Result<TransportMessage<'c>, TransportError>> + 'c {
+        let mut failed = false;
+        core::iter::from_fn(move || {
+            if failed || reader.remaining() == 0 {
+                return None;
+            }
+
+            let before = reader.remaining();
+            let msg = Self::decode(reader);
+            // Reads never succeed without consuming, so neither does a decode
+            debug_assert!(msg.is_err() || reader.remaining() < before);
+            failed = msg.is_err();
+            Some(msg)
+        })
+    }
 }
 
-// Global only cookie
-// #[derive(PartialEq, Eq)]
-// pub struct Cookie {
-//     cookie: [u8; 1024],
-//     len: usize,
-// }
-
-// #[cfg(feature = "defmt")]
-// impl defmt::Format for Cookie {
-//     fn format(&self, fmt: defmt::Formatter) {
-//         defmt::write!(fmt, "{=[u8]:?}", &self.cookie[..self.len]);
-//     }
-// }
-
-// impl Cookie {
-//     pub fn as_slice(&self) -> &[u8] {
-//         &self.cookie[..self.len]
-//     }
-
-// fn from_slice(slice: &[u8]) -> Self {
-//     let mut cookie = [0u8; 1024];
-//     cookie[..slice.len()].copy_from_slice(slice);
-//     Cookie {
-//         cookie,
-//         len: slice.len(),
-//     }
-// }
-// }
-
-// impl core::fmt::Debug for Cookie {
-//     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-//         f.debug_list()
-//             .entries(self.cookie[..self.len].iter())
-//             .finish()
-//     }
-// }
-
-// impl core::fmt::Debug for Box<Cookie> {
-//     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-
-//     }
-// }
-// box_pool!(CookieStorage: Cookie);
-
-// static CookieStorage:
-
-// impl CookieBlock {
-
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{SliceReader, SliceWriter, ZVec};
+    use crate::protocol::{whatami::WhatAmI, ZenohID};
+
+    #[test]
+    fn kind_names_every_body() {
+        let zid = ZenohID::from(0x49);
+        let cases = [
+            (Join::message(WhatAmI::Peer, zid, 2048, 10000, 0), "Join"),
+            (InitSyn::message(WhatAmI::Client, zid), "InitSyn"),
+            (InitSyn::ack(WhatAmI::Router, zid, &[0xc0]), "InitAck"),
+            (
+                TransportMessage {
+                    body: TransportBody::OpenSyn(OpenSyn::new(10000, 0, None)),
+                },
+                "OpenSyn",
+            ),
+            (OpenSyn::ack(10000, 0), "OpenAck"),
+            (Close::message(true, 0), "Close"),
+            (
+                TransportMessage {
+                    body: TransportBody::KeepAlive,
+                },
+                "KeepAlive",
+            ),
+            (Frame::message(true, 0), "Frame"),
+            (
+                TransportMessage {
+                    body: TransportBody::Fragment,
+                },
+                "Fragment",
+            ),
+        ];
+
+        for (msg, kind) in cases {
+            assert_eq!(msg.body.kind(), kind);
+        }
+    }
+
+    #[test]
+    fn unimplemented_bodies_fail_to_encode() {
+        let mut buf = [0u8; 16];
+        for body in [TransportBody::KeepAlive, TransportBody::Fragment] {
+            let mut writer = SliceWriter::new(&mut buf);
+            assert!(matches!(
+                TransportMessage { body }.encode(&mut writer),
+                Err(TransportError::UnsupportedMessage)
+            ));
+            assert!(writer.is_empty());
+        }
+    }
+
+    #[test]
+    fn chunks_concatenate_to_the_full_encoding() {
+        let msg = InitSyn::ack(WhatAmI::Router, ZenohID::from(0x49), &[0xc0, 0x01, 0x02]);
+        let mut vec = ZVec::new();
+        let len = msg.encode(&mut vec).unwrap();
+
+        let mut chunks = std::vec::Vec::new();
+        let mut concat = std::vec::Vec::new();
+        let chunked = msg
+            .encode_chunks(|chunk| {
+                chunks.push(chunk.len());
+                concat.extend_from_slice(chunk);
+            })
+            .unwrap();
+
+        assert!(chunks.len() > 1);
+        assert_eq!(chunked, len);
+        assert_eq!(concat, vec.as_slice());
+    }
+
+    #[test]
+    fn unknown_header_is_reported() {
+        let reader = SliceReader::new(&[0x1f, 0x00]);
+        assert!(matches!(
+            TransportMessage::decode(&reader),
+            Err(TransportError::UnknownMessageId(0x1f))
+        ));
+    }
+
+    #[cfg(feature = "defmt")]
+    #[test]
+    fn errors_can_be_logged_with_defmt() {
+        fn is_format<T: defmt::Format>() {}
+
+        is_format::<TransportError>();
+        is_format::<crate::link::LinkError>();
+        is_format::<crate::protocol::ProtocolError>();
+        is_format::<crate::iobuf::WriteError>();
+        is_format::<crate::iobuf::DidntRead>();
+        is_format::<crate::transport::UnicastParams>();
+    }
+
+    #[test]
+    fn decode_iter_yields_each_message_of_a_batch() {
+        let zid = ZenohID::from(0x49);
+        let mut vec = ZVec::new();
+        InitSyn::message(WhatAmI::Client, zid)
+            .encode(&mut vec)
+            .unwrap();
+        OpenSyn::ack(10000, 7).encode(&mut vec).unwrap();
+        let len = vec.len();
+
+        let reader = vec.extract_slice(len).unwrap();
+        let msgs: std::vec::Vec<_> = TransportMessage::decode_iter(&reader)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            msgs,
+            [
+                InitSyn::message(WhatAmI::Client, zid),
+                OpenSyn::ack(10000, 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_iter_stops_on_a_truncated_message() {
+        let mut buf = [0u8; 64];
+        let mut writer = SliceWriter::new(&mut buf);
+        Close::message(true, 0).encode(&mut writer).unwrap();
+        InitSyn::message(WhatAmI::Client, ZenohID::from(0x49))
+            .encode(&mut writer)
+            .unwrap();
+        let len = writer.len();
+
+        let reader = SliceReader::new(&buf[..len - 1]);
+        let mut msgs = TransportMessage::decode_iter(&reader);
+        assert!(matches!(msgs.next(), Some(Ok(_))));
+        assert!(matches!(msgs.next(), Some(Err(_))));
+        assert!(msgs.next().is_none());
+    }
+}
@@ -35,7 +35,7 @@ use crate::{
     transport::TransportError,
 };
 
-use super::TransportMessage;
+use super::{frame, TransportMessage};
 
 pub(crate) const Z_MID_T_OPEN: u8 = 0x02;
 
@@ -48,12 +48,12 @@ pub mod flag {
 #[derive(Debug, PartialEq, Eq)]
 pub struct OpenSyn<'a> {
     pub lease: u32,
-    pub initial_sn: u32,
+    pub initial_sn: u64,
     pub cookie: Option<&'a [u8]>,
 }
 
 impl<'a> OpenSyn<'a> {
-    pub fn new(lease: u32, initial_sn: u32, cookie: Option<&'a [u8]>) -> Self {
+    pub fn new(lease: u32, initial_sn: u64, cookie: Option<&'a [u8]>) -> Self {
         Self {
             lease,
             initial_sn,
@@ -61,10 +61,19 @@ impl<'a> OpenSyn<'a> {
         }
     }
 
+    /// An OpenAck, as sent by the accepting side.
+    pub fn ack(lease: u32, initial_sn: u64) -> TransportMessage<'a> {
+        TransportMessage {
+            body: TransportBody::OpenAck(OpenSyn::new(lease, initial_sn, None)),
+        }
+    }
+
+    /// Leases of whole seconds are sent in seconds with the T flag, any
+    /// other lease in milliseconds.
     pub fn header(&self) -> u8 {
         let mut header = Z_MID_T_OPEN;
 
-        if (self.lease % 1000) == 0 {
+        if self.lease != 0 && self.lease.is_multiple_of(1000) {
             header |= flag::T;
         }
 
@@ -72,10 +81,26 @@ impl<'a> OpenSyn<'a> {
     }
 
     pub fn encode<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        self.encode_with(writer, false)
+    }
+
+    /// Encodes the message as an OpenAck, with the A flag and no cookie.
+    pub fn encode_ack<W: Writer>(&self, writer: &mut W) -> Result<(), TransportError> {
+        self.encode_with(writer, true)
+    }
+
+    fn encode_with<W: Writer>(&self, writer: &mut W, ack: bool) -> Result<(), TransportError> {
         #[cfg(feature = "defmt")]
         defmt::debug!("Encoding _Z_MID_T_OPEN");
 
-        let header = self.header();
+        if self.lease == 0 {
+            return Err(TransportError::InvalidLease);
+        }
+
+        let mut header = self.header();
+        if ack {
+            header |= flag::A;
+        }
 
         writer.write_u8(header)?;
 
@@ -85,7 +110,7 @@ impl<'a> OpenSyn<'a> {
             Varint::<u64>::encode(writer, self.lease as u64)?;
         }
 
-        Varint::<u64>::encode(writer, self.initial_sn as u64)?;
+        Varint::<u64>::encode(writer, self.initial_sn)?;
 
         if header & flag::A == 0 {
             if let Some(cookie) = self.cookie {
@@ -98,20 +123,25 @@ impl<'a> OpenSyn<'a> {
     }
 
     pub fn decode<R: Reader>(
-        reader: &mut R,
+        reader: &'a R,
         header: u8,
-    ) -> Result<TransportMessage, TransportError> {
+    ) -> Result<TransportMessage<'a>, TransportError> {
         #[cfg(feature = "defmt")]
         defmt::debug!("Decoding _Z_MID_T_OPEN");
 
         let lease = Varint::<u32>::decode(reader)?;
         let lease = if header & flag::T == flag::T {
-            lease * 1000
+            lease
+                .checked_mul(1000)
+                .ok_or(TransportError::InvalidLease)?
         } else {
             lease
         };
+        if lease == 0 {
+            return Err(TransportError::InvalidLease);
+        }
 
-        let initial_sn = Varint::<u32>::decode(reader)?;
+        let initial_sn = Varint::<u64>::decode(reader)?;
         let cookie = if header & flag::A == flag::A {
             None
         } else {
@@ -121,7 +151,7 @@ impl<'a> OpenSyn<'a> {
         };
 
         if header & flag::Z == flag::Z {
-            unimplemented!()
+            frame::ext::skip_all(reader)?;
         }
 
         if header & flag::A == flag::A {
@@ -143,3 +173,106 @@ impl<'a> OpenSyn<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iobuf::{DidntRead, SliceReader, SliceWriter};
+
+    #[test]
+    fn whole_second_lease_is_sent_in_seconds() {
+        let mut buf = [0u8; 16];
+        let mut writer = SliceWriter::new(&mut buf);
+        OpenSyn::new(5000, 0, Some(&[0xc0]))
+            .encode(&mut writer)
+            .unwrap();
+        let len = writer.len();
+
+        assert_eq!(buf[0], Z_MID_T_OPEN | flag::T);
+        assert_eq!(&buf[1..len], &[5, 0, 1, 0xc0]);
+    }
+
+    #[test]
+    fn sub_second_lease_is_sent_in_milliseconds() {
+        let mut buf = [0u8; 16];
+        let mut writer = SliceWriter::new(&mut buf);
+        OpenSyn::new(500, 0, None).encode(&mut writer).unwrap();
+        let len = writer.len();
+
+        assert_eq!(buf[0], Z_MID_T_OPEN);
+        // 500 as a varint
+        assert_eq!(&buf[1..len], &[0xf4, 0x03, 0]);
+
+        let mut writer = SliceWriter::new(&mut buf);
+        OpenSyn::new(1000, 0, None).encode(&mut writer).unwrap();
+        let len = writer.len();
+
+        assert_eq!(buf[0], Z_MID_T_OPEN | flag::T);
+        assert_eq!(&buf[1..len], &[1, 0]);
+    }
+
+    #[test]
+    fn zero_lease_is_rejected() {
+        let mut buf = [0u8; 16];
+        let mut writer = SliceWriter::new(&mut buf);
+        assert!(matches!(
+            OpenSyn::new(0, 0, None).encode(&mut writer),
+            Err(TransportError::InvalidLease)
+        ));
+
+        // Neither in milliseconds nor in seconds
+        for header in [Z_MID_T_OPEN | flag::A, Z_MID_T_OPEN | flag::A | flag::T] {
+            let reader = SliceReader::new(&[0, 0]);
+            assert!(matches!(
+                OpenSyn::decode(&reader, header),
+                Err(TransportError::InvalidLease)
+            ));
+        }
+    }
+
+    #[test]
+    fn lease_overflowing_in_milliseconds_is_rejected() {
+        // u32::MAX seconds, initial SN 0
+        let reader = SliceReader::new(&[0xff, 0xff, 0xff, 0xff, 0x0f, 0]);
+        assert!(matches!(
+            OpenSyn::decode(&reader, Z_MID_T_OPEN | flag::A | flag::T),
+            Err(TransportError::InvalidLease)
+        ));
+    }
+
+    #[test]
+    fn extensions_are_skipped() {
+        // lease 1s, initial SN 7, then a Z64 and a unit extension
+        let reader = SliceReader::new(&[1, 7, frame::ext::Z | 0x21, 0x05, 0x02]);
+        let msg = OpenSyn::decode(&reader, Z_MID_T_OPEN | flag::A | flag::T | flag::Z).unwrap();
+        assert_eq!(msg, OpenSyn::ack(1000, 7));
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    /// Hands out copies only, like a reader over a peripheral FIFO.
+    struct CopyReader<'a>(SliceReader<'a>);
+
+    impl Reader for CopyReader<'_> {
+        fn read(&self, into: &mut [u8]) -> Result<core::num::NonZeroUsize, DidntRead> {
+            self.0.read(into)
+        }
+
+        fn read_exact(&self, into: &mut [u8]) -> Result<(), DidntRead> {
+            self.0.read_exact(into)
+        }
+
+        fn remaining(&self) -> usize {
+            self.0.remaining()
+        }
+    }
+
+    #[test]
+    fn cookie_needs_an_in_place_reader() {
+        // lease 1s, initial SN 0, one byte cookie
+        let reader = CopyReader(SliceReader::new(&[1, 0, 1, 0xc0]));
+        assert!(matches!(
+            OpenSyn::decode(&reader, Z_MID_T_OPEN | flag::T),
+            Err(TransportError::DecodeError(_))
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use super::ProtocolError;
+
 #[repr(u8)]
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -22,14 +24,18 @@ impl WhatAmI {
     }
 }
 
-impl From<u8> for WhatAmI {
+/// Reads the whatami from the two low bits of `b`, the reserved `0b11` is
+/// rejected.
+impl TryFrom<u8> for WhatAmI {
+    type Error = ProtocolError;
+
     #[inline]
-    fn from(b: u8) -> Self {
+    fn try_from(b: u8) -> Result<Self, Self::Error> {
         match b & 0b0000_0011 {
-            0b00 => WhatAmI::Router,
-            0b01 => WhatAmI::Peer,
-            0b10 => WhatAmI::Client,
-            _ => unreachable!(),
+            0b00 => Ok(WhatAmI::Router),
+            0b01 => Ok(WhatAmI::Peer),
+            0b10 => Ok(WhatAmI::Client),
+            w => Err(ProtocolError::BadWhatAmI(w)),
         }
     }
 }
@@ -0,0 +1,165 @@
+use crate::iobuf::{Writer, ZVec};
+use crate::protocol::network::NetworkMessage;
+use crate::protocol::reliability::Reliability;
+use crate::protocol::transport::frame::Frame;
+
+use super::TransportError;
+
+/// A network message that can be appended to a batch, either still to be
+/// encoded or already encoded by the application.
+pub(crate) trait BatchItem {
+    fn encode_into(&self, buf: &mut ZVec) -> Result<(), TransportError>;
+}
+
+impl BatchItem for NetworkMessage<'_> {
+    fn encode_into(&self, buf: &mut ZVec) -> Result<(), TransportError> {
+        self.encode(buf)
+    }
+}
+
+impl BatchItem for [u8] {
+    fn encode_into(&self, buf: &mut ZVec) -> Result<(), TransportError> {
+        buf.write(self)?;
+        Ok(())
+    }
+}
+
+/// Accumulates network messages behind a single Frame header.
+///
+/// Messages are only held back while a batch has been started explicitly or
+/// when a flush window is configured, in which case the batch is due once
+/// `window_ms` have elapsed since its first message.
+pub(crate) struct TxBatch {
+    buf: ZVec,
+    manual: bool,
+    window_ms: u32,
+    age_ms: u32,
+    qos: Option<u8>,
+    reliability: Reliability,
+}
+
+impl TxBatch {
+    pub fn new() -> Self {
+        TxBatch {
+            buf: ZVec::new(),
+            manual: false,
+            window_ms: 0,
+            age_ms: 0,
+            qos: None,
+            reliability: Reliability::Reliable,
+        }
+    }
+
+    pub fn set_window(&mut self, window_ms: u32) {
+        self.window_ms = window_ms;
+    }
+
+    pub fn begin(&mut self) {
+        self.manual = true;
+    }
+
+    /// Whether pushed messages should be held back rather than sent right away.
+    pub fn is_batching(&self) -> bool {
+        self.manual || self.window_ms > 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.buf.as_slice()
+    }
+
+    /// Ends an explicitly started batch.
+    pub fn end(&mut self) {
+        self.manual = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.age_ms = 0;
+    }
+
+    /// Appends `msg`, opening a new frame with `sn`, `qos` and `reliability`
+    /// when the batch is empty.
+    ///
+    /// Returns `Ok(false)` and leaves the batch untouched if the message would
+    /// make the batch exceed `limit` bytes or belongs to another conduit or
+    /// reliability than the pending frame.
+    pub fn push<M: BatchItem + ?Sized>(
+        &mut self,
+        sn: u64,
+        qos: Option<u8>,
+        reliability: Reliability,
+        msg: &M,
+        limit: usize,
+    ) -> Result<bool, TransportError> {
+        let mark = self.buf.len();
+
+        if mark != 0 && (qos != self.qos || reliability != self.reliability) {
+            return Ok(false);
+        }
+
+        let encoded = if mark == 0 {
+            self.qos = qos;
+            self.reliability = reliability;
+            Frame::message_with_qos(reliability.is_reliable(), sn, qos)
+                .encode(&mut self.buf)
+                .and_then(|_| msg.encode_into(&mut self.buf))
+        } else {
+            msg.encode_into(&mut self.buf)
+        };
+
+        match encoded {
+            Ok(()) if self.buf.len() <= limit => Ok(true),
+            Ok(()) | Err(TransportError::EncodeError(_)) => {
+                self.buf.truncate(mark);
+                Ok(false)
+            }
+            Err(e) => {
+                self.buf.truncate(mark);
+                Err(e)
+            }
+        }
+    }
+
+    /// Ages the batch by `elapsed_ms` and reports whether its window has expired.
+    pub fn tick(&mut self, elapsed_ms: u32) -> bool {
+        if self.is_empty() || self.window_ms == 0 {
+            return false;
+        }
+
+        self.age_ms = self.age_ms.saturating_add(elapsed_ms);
+        self.age_ms >= self.window_ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::keyexpr::KeyExpr;
+    use crate::protocol::network::push::Push;
+    use crate::protocol::transport::frame::flag;
+
+    #[test]
+    fn reliability_sets_the_frame_r_flag() {
+        let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/a").unwrap(), b"x"));
+
+        let mut batch = TxBatch::new();
+        assert!(batch
+            .push(0, None, Reliability::Reliable, &msg, 64)
+            .unwrap());
+        assert_eq!(batch.as_slice()[0] & flag::R, flag::R);
+        // A best effort message cannot share the reliable frame
+        assert!(!batch
+            .push(0, None, Reliability::BestEffort, &msg, 64)
+            .unwrap());
+
+        batch.clear();
+        assert!(batch
+            .push(0, None, Reliability::BestEffort, &msg, 64)
+            .unwrap());
+        assert_eq!(batch.as_slice()[0] & flag::R, 0);
+    }
+}
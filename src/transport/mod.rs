@@ -1,23 +1,37 @@
 use crate::{
-    link::{Endpoint, LinkIntf, TransportCap},
-    protocol::whatami::WhatAmI,
-    Config,
+    link::{Endpoint, Link, LinkCapabilities, LinkIntf, TransportCap},
+    protocol::{
+        network::NetworkMessage, priority::Priority, reliability::Reliability,
+        transport::close::CloseReason, whatami::WhatAmI, ZenohID,
+    },
+    Config, Z_MAX_MTU,
 };
 use thiserror::Error;
 
+mod batch;
+mod multicast;
+mod txqueue;
 mod unicast;
 
+pub use multicast::MulticastPeer;
+pub use txqueue::TxQueue;
+pub use unicast::{resolution_bits, HandshakeStats, UnicastParams, UnicastState};
+
+// Without an allocator the larger multicast state cannot be boxed, and a
+// session only ever holds one transport.
+#[allow(clippy::large_enum_variant)]
 pub enum Transport<L> {
     Unicast(unicast::Unicast<L>),
-    Multicast,
+    Multicast(multicast::Multicast<L>),
 }
 
 #[derive(Debug, Error)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TransportError {
     #[error("Link Error")]
     LinkError(#[from] crate::link::LinkError),
     #[error("Encode Error")]
-    EncodeError(#[from] crate::iobuf::DidntWrite),
+    EncodeError(#[from] crate::iobuf::WriteError),
     #[error("Decode Error")]
     DecodeError(#[from] crate::iobuf::DidntRead),
     #[error("More cookie been allocated")]
@@ -26,6 +40,70 @@ pub enum TransportError {
     UnexpectMsg,
     #[error("Unexpect open sn resolution")]
     OpenSnResolution,
+    #[error("Peer table full")]
+    PeerTableFull,
+    #[error("Protocol Error")]
+    ProtocolError(#[from] crate::protocol::ProtocolError),
+    #[error("Unsupported message")]
+    UnsupportedMessage,
+    #[error("Unknown message id")]
+    UnknownMessageId(u8),
+    /// The peer marked an extension we do not understand as mandatory, the
+    /// message carrying it cannot be processed.
+    #[error("Unknown mandatory extension {0}")]
+    UnknownMandatoryExtension(u8),
+    /// An extension was sent with the reserved encoding, its body cannot be
+    /// skipped.
+    #[error("Reserved encoding for extension {0}")]
+    ReservedExtensionEncoding(u8),
+    #[error("Handshake byte budget exceeded")]
+    HandshakeBudgetExceeded,
+    #[error("Cookie of {0} bytes exceeds Z_MAX_COOKIE_SIZE")]
+    CookieTooLarge(usize),
+    #[error("InitAck without cookie")]
+    MissingCookie,
+    #[error("Cookie rejected by the peer")]
+    CookieMismatch,
+    #[error("Unsupported mode")]
+    UnsupportedMode(WhatAmI),
+    /// A reliable frame did not carry the SN following the previous one, the
+    /// messages in between were lost.
+    #[error("Sequence gap")]
+    SequenceGap { expected: u64, got: u64 },
+    #[error("QoS not negotiated")]
+    QosNotNegotiated,
+    #[error("Unsupported link")]
+    UnsupportedLink,
+    #[error("Invalid batch size")]
+    InvalidBatchSize,
+    /// A lease of 0 would expire the transport immediately, one of more than
+    /// u32::MAX milliseconds cannot be represented.
+    #[error("Invalid lease")]
+    InvalidLease,
+    /// A stream link can only frame messages up to 65535 bytes.
+    #[error("Message too long")]
+    MessageTooLong(usize),
+    #[error("Tx queue full")]
+    TxQueueFull,
+    /// The link MTU does not fit in the transport buffers of `Z_MAX_MTU` bytes.
+    #[error("Link MTU of {0} bytes exceeds Z_MAX_MTU")]
+    MtuExceedsBuffer(usize),
+    #[error("Transport not open")]
+    NotOpen,
+    /// Half the SN space is taken by unacknowledged reliable frames.
+    #[error("Reliable window full")]
+    WindowFull,
+}
+
+/// Opens the link of `ep`, refusing links whose messages would not fit in
+/// the transport buffers.
+fn open_link<L: LinkIntf, E: Endpoint<L = L>>(ep: E) -> Result<Link<L>, TransportError> {
+    let zl = crate::link::open(ep)?;
+    if zl.mtu > Z_MAX_MTU {
+        return Err(TransportError::MtuExceedsBuffer(zl.mtu));
+    }
+
+    Ok(zl)
 }
 
 fn new_client<L: LinkIntf, E: Endpoint<L = L>>(
@@ -35,30 +113,242 @@ fn new_client<L: LinkIntf, E: Endpoint<L = L>>(
     #[cfg(feature = "defmt")]
     defmt::debug!("Opening link");
 
-    let zl = crate::link::open(ep)?;
+    let zl = open_link(ep)?;
     match zl.cap.transport() {
-        TransportCap::Unicast => {
+        // A raw Ethernet link reaches a single peer, the handshake runs over
+        // its frames like over any other datagram link.
+        TransportCap::Unicast | TransportCap::Raweth => {
             let mut unicast = unicast::Unicast::new(zl);
-            let params = unicast.handshake(cfg.mode, cfg.id)?;
-            unicast.update(&params)?;
+            let params = unicast.handshake(cfg)?;
+            unicast.update(params)?;
+            unicast.set_batch_window(cfg.batch_window_ms);
+            unicast.set_ack_window(cfg.ack_window);
             Ok(Transport::Unicast(unicast))
         }
         TransportCap::Multicast => {
-            unimplemented!()
-        }
-        _ => {
-            unimplemented!()
+            let mut multicast = multicast::Multicast::new(zl, cfg.mode, cfg.id);
+            multicast.set_batch_window(cfg.batch_window_ms);
+            multicast.send_join()?;
+            Ok(Transport::Multicast(multicast))
         }
     }
 }
 
 impl<L: LinkIntf> Transport<L> {
     pub fn new<E: Endpoint<L = L>>(ep: E, cfg: &Config) -> Result<Transport<L>, TransportError> {
+        // A peer opens its transports exactly like a client, only the
+        // whatami it announces differs.
         match cfg.mode {
-            WhatAmI::Client => new_client(ep, cfg),
-            _ => {
-                unimplemented!()
+            WhatAmI::Client | WhatAmI::Peer => new_client(ep, cfg),
+            WhatAmI::Router => Err(TransportError::UnsupportedMode(cfg.mode)),
+        }
+    }
+
+    /// Opens the link of `ep` and runs the unicast handshake only, returning
+    /// the negotiated parameters. No Close is sent afterwards.
+    pub fn handshake_only<E: Endpoint<L = L>>(
+        ep: E,
+        cfg: &Config,
+    ) -> Result<UnicastParams, TransportError> {
+        let zl = open_link(ep)?;
+        match zl.cap.transport() {
+            TransportCap::Unicast | TransportCap::Raweth => {
+                unicast::Unicast::new(zl).handshake(cfg)
             }
+            TransportCap::Multicast => Err(TransportError::UnsupportedLink),
+        }
+    }
+
+    /// The remote end of a unicast transport, multicast transports have no single peer.
+    pub fn peer(&self) -> Option<(ZenohID, WhatAmI)> {
+        match self {
+            Transport::Unicast(unicast) => Some(unicast.peer()),
+            Transport::Multicast(_) => None,
+        }
+    }
+
+    pub fn link_capabilities(&self) -> LinkCapabilities {
+        match self {
+            Transport::Unicast(unicast) => unicast.link_capabilities(),
+            Transport::Multicast(multicast) => multicast.link_capabilities(),
+        }
+    }
+
+    /// Re-opens the link after a failure, keeping the ZID of `cfg`.
+    pub fn reconnect(&mut self, cfg: &Config) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.reconnect(cfg),
+            Transport::Multicast(multicast) => multicast.reconnect(),
+        }
+    }
+
+    pub fn send(&mut self, msg: &NetworkMessage) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.send(msg),
+            Transport::Multicast(multicast) => multicast.send(msg),
+        }
+    }
+
+    /// Appends `msg` to the pending batch without sending it, unless the
+    /// batch is full. Only the default `priority` is allowed without QoS.
+    pub fn queue(
+        &mut self,
+        msg: &NetworkMessage,
+        priority: Priority,
+        reliability: Reliability,
+    ) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.queue_on(msg, priority, reliability),
+            Transport::Multicast(multicast) => multicast.queue(msg, priority, reliability),
+        }
+    }
+
+    /// Like [`Transport::queue`] for a network message encoded beforehand,
+    /// e.g. one taken from a [`TxQueue`].
+    pub fn queue_encoded(
+        &mut self,
+        msg: &[u8],
+        priority: Priority,
+        reliability: Reliability,
+    ) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.queue_encoded(msg, priority, reliability),
+            Transport::Multicast(multicast) => multicast.queue_encoded(msg, priority, reliability),
+        }
+    }
+
+    /// Sends the pending batch, if any, without ending an explicit batch.
+    pub fn flush(&mut self) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.flush(),
+            Transport::Multicast(multicast) => multicast.flush(),
+        }
+    }
+
+    pub fn recv<F: FnMut(&NetworkMessage)>(&mut self, on_msg: F) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.recv(on_msg),
+            Transport::Multicast(multicast) => multicast.recv(on_msg),
         }
     }
+
+    pub fn last_close_reason(&self) -> Option<CloseReason> {
+        match self {
+            Transport::Unicast(unicast) => unicast.last_close_reason(),
+            Transport::Multicast(multicast) => multicast.last_close_reason(),
+        }
+    }
+
+    pub fn begin_batch(&mut self) {
+        match self {
+            Transport::Unicast(unicast) => unicast.begin_batch(),
+            Transport::Multicast(multicast) => multicast.begin_batch(),
+        }
+    }
+
+    pub fn flush_batch(&mut self) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.flush_batch(),
+            Transport::Multicast(multicast) => multicast.flush_batch(),
+        }
+    }
+
+    pub fn tick(&mut self, elapsed_ms: u32) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.tick(elapsed_ms),
+            Transport::Multicast(multicast) => multicast.tick(elapsed_ms),
+        }
+    }
+
+    pub fn close(&mut self, reason: CloseReason) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.close(reason),
+            Transport::Multicast(multicast) => multicast.close(reason),
+        }
+    }
+
+    /// Releases `frames` reliable frames from the unicast window, multicast
+    /// transports keep no window.
+    pub fn acknowledge(&mut self, frames: u64) {
+        if let Transport::Unicast(unicast) = self {
+            unicast.acknowledge(frames);
+        }
+    }
+
+    pub fn drain(&mut self, timeout_ms: u32) -> Result<(), TransportError> {
+        match self {
+            Transport::Unicast(unicast) => unicast.drain(timeout_ms),
+            Transport::Multicast(multicast) => multicast.drain(timeout_ms),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::link::mock::{MockIo, MockLink};
+
+    #[test]
+    fn mtu_larger_than_the_buffers_is_refused() {
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let cfg = Config::new(ZenohID::from(0x49), WhatAmI::Client);
+
+        let link = MockLink::unicast(&io).with_mtu(4096);
+        assert!(matches!(
+            Transport::handshake_only(link, &cfg),
+            Err(TransportError::MtuExceedsBuffer(4096))
+        ));
+        // Nothing was sent
+        assert!(io.borrow().outbound().is_empty());
+    }
+
+    #[test]
+    fn handshake_only_leaves_the_link_open() {
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let cfg = Config::new(ZenohID::from(0x49), WhatAmI::Client);
+
+        let params = Transport::handshake_only(MockLink::unicast(&io), &cfg).unwrap();
+        assert_eq!(params.zid, ZenohID::from(0xbeef));
+        assert_eq!(params.whatami, WhatAmI::Router);
+        // InitSyn and OpenSyn, no Close
+        assert_eq!(io.borrow().outbound().len(), 2);
+    }
+
+    #[test]
+    fn peer_mode_opens_like_a_client() {
+        use crate::iobuf::SliceReader;
+        use crate::protocol::transport::{TransportBody, TransportMessage};
+
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let cfg = Config::new(ZenohID::from(0x49), WhatAmI::Peer);
+        let transport = Transport::new(MockLink::unicast(&io), &cfg).unwrap();
+        assert_eq!(
+            transport.peer(),
+            Some((ZenohID::from(0xbeef), WhatAmI::Router))
+        );
+
+        let io = io.borrow();
+        let reader = SliceReader::new(&io.outbound()[0]);
+        let TransportBody::InitSyn(ism) = TransportMessage::decode(&reader).unwrap().body else {
+            panic!("expected an InitSyn");
+        };
+        assert_eq!(ism.whatami(), WhatAmI::Peer);
+
+        let cfg = Config::new(ZenohID::from(0x49), WhatAmI::Router);
+        assert!(matches!(
+            Transport::new(MockLink::unicast(&RefCell::new(MockIo::new())), &cfg),
+            Err(TransportError::UnsupportedMode(WhatAmI::Router))
+        ));
+    }
 }
@@ -0,0 +1,341 @@
+use heapless::FnvIndexMap;
+
+use crate::iobuf::{WriteError, ZVec};
+use crate::link::{Link, LinkCapabilities, LinkError, LinkIntf};
+use crate::protocol::network::NetworkMessage;
+use crate::protocol::transport::close::{Close, CloseReason};
+use crate::protocol::transport::frame::Frame;
+use crate::protocol::transport::join::Join;
+use crate::protocol::transport::{TransportBody, TransportMessage};
+use crate::protocol::{priority::Priority, reliability::Reliability, whatami::WhatAmI, ZenohID};
+use crate::{
+    Z_BATCH_MULTICAST_SIZE, Z_JOIN_INTERVAL, Z_MULTICAST_MAX_PEERS, Z_SN_RESOLUTION,
+    Z_TRANSPORT_LEASE,
+};
+
+use super::batch::{BatchItem, TxBatch};
+use super::unicast::_z_sn_modulo_mask;
+use super::TransportError;
+
+pub struct Multicast<L> {
+    intf: Link<L>,
+    cache: ZVec,
+    batch: TxBatch,
+    zid: ZenohID,
+    whatami: WhatAmI,
+    next_sn: u64,
+    sn_mask: u64,
+    since_join: u32,
+    last_close: Option<CloseReason>,
+    peers: FnvIndexMap<ZenohID, MulticastPeer, Z_MULTICAST_MAX_PEERS>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MulticastPeer {
+    pub whatami: WhatAmI,
+    pub batch_size: u16,
+    pub lease: u32,
+    pub next_sn_reliable: u64,
+    pub next_sn_best_effort: u64,
+}
+
+impl<L: LinkIntf> Multicast<L> {
+    pub fn new(intf: Link<L>, whatami: WhatAmI, zid: ZenohID) -> Self {
+        Multicast {
+            intf,
+            cache: ZVec::new(),
+            batch: TxBatch::new(),
+            zid,
+            whatami,
+            next_sn: 0,
+            sn_mask: _z_sn_modulo_mask(Z_SN_RESOLUTION),
+            since_join: 0,
+            last_close: None,
+            peers: FnvIndexMap::new(),
+        }
+    }
+
+    pub fn send_join(&mut self) -> Result<(), TransportError> {
+        let jm = Join::message(
+            self.whatami,
+            self.zid,
+            Z_BATCH_MULTICAST_SIZE,
+            Z_TRANSPORT_LEASE,
+            self.next_sn,
+        );
+
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Sending Z_JOIN");
+
+        self.cache.clear();
+        jm.encode(&mut self.cache)?;
+        self.intf.send_msg(self.cache.as_slice())?;
+        self.cache.clear();
+        self.since_join = 0;
+
+        Ok(())
+    }
+
+    /// Re-opens the link and announces ourselves again, peers have to JOIN
+    /// anew.
+    pub fn reconnect(&mut self) -> Result<(), TransportError> {
+        self.batch.clear();
+        self.peers.clear();
+        self.intf.open()?;
+        self.send_join()
+    }
+
+    pub fn send(&mut self, msg: &NetworkMessage) -> Result<(), TransportError> {
+        self.queue(msg, Priority::default(), Reliability::default())?;
+
+        if !self.batch.is_batching() {
+            self.send_batch()?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends `msg` to the pending batch, which only goes out once full or
+    /// on [`Multicast::flush`]. Multicast never negotiates QoS so only the
+    /// default priority is allowed.
+    pub fn queue(
+        &mut self,
+        msg: &NetworkMessage,
+        priority: Priority,
+        reliability: Reliability,
+    ) -> Result<(), TransportError> {
+        self.queue_item(msg, priority, reliability)
+    }
+
+    /// Like [`Multicast::queue`] for a network message encoded beforehand.
+    pub fn queue_encoded(
+        &mut self,
+        msg: &[u8],
+        priority: Priority,
+        reliability: Reliability,
+    ) -> Result<(), TransportError> {
+        self.queue_item(msg, priority, reliability)
+    }
+
+    fn queue_item<M: BatchItem + ?Sized>(
+        &mut self,
+        msg: &M,
+        priority: Priority,
+        reliability: Reliability,
+    ) -> Result<(), TransportError> {
+        if priority != Priority::default() {
+            return Err(TransportError::QosNotNegotiated);
+        }
+
+        if !self.push(reliability, msg)? {
+            self.send_batch()?;
+            if !self.push(reliability, msg)? {
+                return Err(TransportError::EncodeError(WriteError::DidntWrite));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pushes `msg` into the batch, a new frame takes the next SN which is
+    /// given back if the message does not fit.
+    fn push<M: BatchItem + ?Sized>(
+        &mut self,
+        reliability: Reliability,
+        msg: &M,
+    ) -> Result<bool, TransportError> {
+        if !self.batch.is_empty() {
+            return self
+                .batch
+                .push(self.next_sn, None, reliability, msg, self.intf.mtu);
+        }
+
+        let sn = self.next_sn();
+        let pushed = self.batch.push(sn, None, reliability, msg, self.intf.mtu);
+        if !matches!(pushed, Ok(true)) {
+            self.next_sn = sn;
+        }
+        pushed
+    }
+
+    /// Returns the SN for the next frame and advances the counter, wrapping
+    /// at the resolution announced in our JOIN.
+    pub fn next_sn(&mut self) -> u64 {
+        let sn = self.next_sn;
+        self.next_sn = sn.wrapping_add(1) & self.sn_mask;
+        sn
+    }
+
+    /// Sends the pending batch, if any.
+    pub fn flush(&mut self) -> Result<(), TransportError> {
+        self.send_batch()
+    }
+
+    pub fn begin_batch(&mut self) {
+        self.batch.begin();
+    }
+
+    pub fn flush_batch(&mut self) -> Result<(), TransportError> {
+        self.batch.end();
+        self.send_batch()
+    }
+
+    fn send_batch(&mut self) -> Result<(), TransportError> {
+        if !self.batch.is_empty() {
+            let sent = self.intf.send_msg(self.batch.as_slice());
+            // Part of the frame left, it cannot be sent again as is
+            if let Err(TransportError::LinkError(LinkError::PartialWrite { .. })) = sent {
+                self.batch.clear();
+            }
+            sent?;
+        }
+        self.batch.clear();
+
+        Ok(())
+    }
+
+    pub fn close(&mut self, reason: CloseReason) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Sending Z_CLOSE");
+
+        self.cache.clear();
+        Close::message(true, reason.into()).encode(&mut self.cache)?;
+        self.intf.send_msg(self.cache.as_slice())?;
+        self.cache.clear();
+
+        Ok(())
+    }
+
+    pub fn drain(&mut self, timeout_ms: u32) -> Result<(), TransportError> {
+        self.intf.drain(timeout_ms)
+    }
+
+    pub fn set_batch_window(&mut self, window_ms: u32) {
+        self.batch.set_window(window_ms);
+    }
+
+    /// Advances the JOIN timer by `elapsed_ms`, sending a new JOIN once the
+    /// interval has expired. The pending batch is flushed once its window has elapsed.
+    pub fn tick(&mut self, elapsed_ms: u32) -> Result<(), TransportError> {
+        if self.batch.tick(elapsed_ms) {
+            self.send_batch()?;
+        }
+
+        self.since_join = self.since_join.saturating_add(elapsed_ms);
+        if self.since_join >= Z_JOIN_INTERVAL {
+            self.send_join()?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads one batch from the link, JOINs update the peer table while the
+    /// network messages carried by a Frame are handed to `on_msg`.
+    pub fn recv<F: FnMut(&NetworkMessage)>(&mut self, mut on_msg: F) -> Result<(), TransportError> {
+        self.cache.clear();
+        let mut s = self.cache.extract_slice(self.intf.mtu)?;
+        let size = self.intf.recv_msg(s.as_mut())?;
+        s.truncate(size)?;
+
+        let jm = match TransportMessage::decode(&s)?.body {
+            TransportBody::Join(jm) => jm,
+            TransportBody::Frame(_) => {
+                for msg in Frame::messages(&s) {
+                    on_msg(&msg?);
+                }
+                return Ok(());
+            }
+            TransportBody::Close(close) => {
+                #[cfg(feature = "defmt")]
+                defmt::debug!("Received Z_CLOSE, reason {}", close.reason);
+                self.last_close = Some(close.reason.into());
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+
+        if jm.zid != self.zid {
+            #[cfg(feature = "defmt")]
+            defmt::debug!("Received Z_JOIN from {}", jm.zid);
+
+            let peer = MulticastPeer {
+                whatami: jm.whatami,
+                batch_size: jm.batch_size,
+                lease: jm.lease,
+                next_sn_reliable: jm.next_sn_reliable,
+                next_sn_best_effort: jm.next_sn_best_effort,
+            };
+            self.peers
+                .insert(jm.zid, peer)
+                .map_err(|_| TransportError::PeerTableFull)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reason of the last Close received from any peer.
+    pub fn last_close_reason(&self) -> Option<CloseReason> {
+        self.last_close
+    }
+
+    pub fn link_capabilities(&self) -> LinkCapabilities {
+        self.intf.cap
+    }
+
+    pub fn peer(&self, zid: &ZenohID) -> Option<&MulticastPeer> {
+        self.peers.get(zid)
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = (&ZenohID, &MulticastPeer)> {
+        self.peers.iter()
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::iobuf::SliceReader;
+    use crate::link::mock::{MockIo, MockLink};
+    use crate::protocol::{keyexpr::KeyExpr, network::push::Push};
+    use crate::transport::open_link;
+
+    fn multicast(io: &RefCell<MockIo>) -> Multicast<MockLink<'_>> {
+        let link = open_link(MockLink::multicast(io)).unwrap();
+        Multicast::new(link, WhatAmI::Peer, ZenohID::from(0x49))
+    }
+
+    fn sent_sn(frame: &[u8]) -> u64 {
+        let reader = SliceReader::new(frame);
+        match TransportMessage::decode(&reader).unwrap().body {
+            TransportBody::Frame(frame) => frame.sn,
+            _ => panic!("expected a Frame"),
+        }
+    }
+
+    #[test]
+    fn every_frame_takes_the_next_sn() {
+        let io = RefCell::new(MockIo::new());
+        let mut transport = multicast(&io);
+
+        let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/a").unwrap(), b"x"));
+        for _ in 0..3 {
+            transport.send(&msg).unwrap();
+        }
+
+        let io = io.borrow();
+        let sns: heapless::Vec<u64, 3> = io.outbound().iter().map(|f| sent_sn(f)).collect();
+        assert_eq!(sns, [0, 1, 2]);
+    }
+
+    #[test]
+    fn next_sn_wraps_at_the_resolution() {
+        let io = RefCell::new(MockIo::new());
+        let mut transport = multicast(&io);
+
+        transport.next_sn = transport.sn_mask;
+        assert_eq!(transport.next_sn(), transport.sn_mask);
+        assert_eq!(transport.next_sn(), 0);
+    }
+}
@@ -0,0 +1,70 @@
+use heapless::{spsc::Queue, Vec};
+
+use crate::{Z_TX_MSG_SIZE, Z_TX_QUEUE_SIZE};
+
+use super::TransportError;
+
+/// Encoded network messages waiting to be framed, e.g. filled from an
+/// interrupt handler and drained from the main loop.
+///
+/// Holds up to `Z_TX_QUEUE_SIZE` messages of at most `Z_TX_MSG_SIZE` bytes.
+pub struct TxQueue {
+    // One slot of a heapless spsc queue is always left empty
+    queue: Queue<Vec<u8, Z_TX_MSG_SIZE>, { Z_TX_QUEUE_SIZE + 1 }>,
+}
+
+impl TxQueue {
+    pub const fn new() -> Self {
+        TxQueue {
+            queue: Queue::new(),
+        }
+    }
+
+    /// Copies `msg` at the back of the queue without blocking.
+    pub fn try_enqueue(&mut self, msg: &[u8]) -> Result<(), TransportError> {
+        let msg = Vec::from_slice(msg).map_err(|_| TransportError::MessageTooLong(msg.len()))?;
+        self.queue
+            .enqueue(msg)
+            .map_err(|_| TransportError::TxQueueFull)
+    }
+
+    pub fn dequeue(&mut self) -> Option<Vec<u8, Z_TX_MSG_SIZE>> {
+        self.queue.dequeue()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+impl Default for TxQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_queue_is_reported() {
+        let mut queue = TxQueue::new();
+        for _ in 0..Z_TX_QUEUE_SIZE {
+            queue.try_enqueue(&[0x01]).unwrap();
+        }
+        assert!(matches!(
+            queue.try_enqueue(&[0x01]),
+            Err(TransportError::TxQueueFull)
+        ));
+
+        assert!(matches!(
+            TxQueue::new().try_enqueue(&[0u8; Z_TX_MSG_SIZE + 1]),
+            Err(TransportError::MessageTooLong(_))
+        ));
+    }
+}
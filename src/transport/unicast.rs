@@ -1,33 +1,92 @@
-use rand::rngs::SmallRng;
-use rand::{Rng, SeedableRng};
+use heapless::Vec;
+#[cfg(feature = "rand")]
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 
-use crate::iobuf::ZVec;
-use crate::link::{Link, LinkIntf};
+use crate::iobuf::{WriteError, ZVec};
+use crate::link::{Link, LinkCapabilities, LinkError, LinkIntf};
+use crate::protocol::network::NetworkMessage;
+use crate::protocol::transport::close::{reason, Close, CloseReason};
+use crate::protocol::transport::frame::Frame;
 use crate::protocol::transport::init::InitSyn;
 use crate::protocol::transport::open::OpenSyn;
 use crate::protocol::transport::{TransportBody, TransportMessage};
-use crate::protocol::{whatami::WhatAmI, ZenohID};
-use crate::Z_TRANSPORT_LEASE;
+use crate::protocol::{
+    priority::Priority, reliability::Reliability, whatami::WhatAmI, ProtocolError, ZenohID,
+};
+use crate::{Config, Z_MAX_COOKIE_SIZE, Z_PATCH, Z_PROTO_VERSION, Z_TRANSPORT_LEASE};
 
+use super::batch::{BatchItem, TxBatch};
 use super::TransportError;
 
 pub struct Unicast<L> {
     intf: Link<L>,
     cache: ZVec,
-    open_cache: ZVec,
+    batch: TxBatch,
+    sn_tx: u64,
+    sn_rx: u64,
+    params: UnicastParams,
+    sn_mask: u64,
+    /// Reliable frames sent and not acknowledged yet, only counted when
+    /// `ack_window` is set.
+    inflight: u64,
+    ack_window: bool,
+    last_close: Option<CloseReason>,
+    state: UnicastState,
 }
 
+/// How far the transport got in opening the session with its peer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UnicastState {
+    /// Not opened yet, or closed by either side.
+    #[default]
+    Closed,
+    /// The InitSyn went out, the handshake has not completed.
+    InitSent,
+    /// The OpenAck was received, user data may flow.
+    Opened,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct UnicastParams {
     pub zid: ZenohID,
     pub batch_size: u16,
-    pub initial_sn_rx: u32,
-    pub initial_sn_tx: u32,
+    pub initial_sn_rx: u64,
+    pub initial_sn_tx: u64,
     pub lease: u32,
     pub whatami: WhatAmI,
+    /// Resolutions are kept as their 2-bit wire code, 0b00..0b11 for
+    /// 8/16/32/64 bits, see [`resolution_bits`].
     pub key_id_res: u8,
     pub req_id_res: u8,
     pub seq_num_res: u8,
     pub is_qos: bool,
+    /// Protocol patch level agreed with the peer, 0 if it asked for one we
+    /// do not support.
+    pub patch: u8,
+    pub stats: HandshakeStats,
+}
+
+/// Bytes exchanged on the link while opening the transport.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HandshakeStats {
+    pub tx_bytes: usize,
+    pub rx_bytes: usize,
+}
+
+impl HandshakeStats {
+    pub fn total(&self) -> usize {
+        self.tx_bytes + self.rx_bytes
+    }
+
+    fn check(&self, max: Option<usize>) -> Result<(), TransportError> {
+        match max {
+            Some(max) if self.total() > max => Err(TransportError::HandshakeBudgetExceeded),
+            _ => Ok(()),
+        }
+    }
 }
 
 impl Default for UnicastParams {
@@ -39,10 +98,12 @@ impl Default for UnicastParams {
             initial_sn_tx: 0,
             lease: Z_TRANSPORT_LEASE,
             whatami: Default::default(),
-            key_id_res: 0,
+            key_id_res: 0x01,
             req_id_res: 0,
             seq_num_res: 0,
             is_qos: false,
+            patch: 0,
+            stats: Default::default(),
         }
     }
 }
@@ -52,41 +113,53 @@ impl<L: LinkIntf> Unicast<L> {
         Unicast {
             intf,
             cache: ZVec::new(),
-            open_cache: ZVec::new(),
+            batch: TxBatch::new(),
+            sn_tx: 0,
+            sn_rx: 0,
+            params: Default::default(),
+            sn_mask: 0,
+            inflight: 0,
+            ack_window: false,
+            last_close: None,
+            state: UnicastState::Closed,
         }
     }
 
-    pub fn handshake(
-        &mut self,
-        whatami: WhatAmI,
-        zid: ZenohID,
-    ) -> Result<UnicastParams, TransportError> {
-        let ism = InitSyn::new(whatami, zid);
+    pub fn handshake(&mut self, cfg: &Config) -> Result<UnicastParams, TransportError> {
+        let mut ism = InitSyn::message(cfg.mode, cfg.id);
         let mut params: UnicastParams = Default::default();
+        self.state = UnicastState::Closed;
 
-        let (seq_num_res, req_id_res, batch_size) = if let TransportMessage {
+        if let TransportMessage {
             body: TransportBody::InitSyn(ism),
-        } = &ism
+        } = &mut ism
         {
-            (ism.seq_num_res, ism.req_id_res, ism.batch_size)
+            ism.batch_size = cfg.batch_size;
+            ism.qos = cfg.qos;
+            params.seq_num_res = ism.seq_num_res;
+            params.req_id_res = ism.req_id_res;
+            params.batch_size = ism.batch_size;
         } else {
             return Err(TransportError::UnexpectMsg);
-        };
-        params.seq_num_res = seq_num_res;
-        params.req_id_res = req_id_res;
-        params.batch_size = batch_size;
+        }
 
         #[cfg(feature = "defmt")]
         defmt::debug!("Sending Z_INIT(Syn)");
 
+        self.cache.clear();
         ism.encode(&mut self.cache)?;
-        self.intf.send_msg(&self.cache.as_slice())?;
+        self.intf.send_msg(self.cache.as_slice())?;
+        self.state = UnicastState::InitSent;
+        params.stats.tx_bytes += self.cache.len();
+        params.stats.check(cfg.max_handshake_bytes)?;
         self.cache.clear();
 
         let mut s = self.cache.extract_slice(self.intf.mtu)?;
         let size = self.intf.recv_msg(s.as_mut())?;
-        s.truncate(size);
-        let iam = TransportMessage::decode(&mut s)?;
+        s.truncate(size)?;
+        params.stats.rx_bytes += size;
+        params.stats.check(cfg.max_handshake_bytes)?;
+        let iam = TransportMessage::decode(&s)?;
 
         let iam = if let TransportMessage {
             body: TransportBody::InitAck(iam),
@@ -98,60 +171,86 @@ impl<L: LinkIntf> Unicast<L> {
         } else {
             return Err(TransportError::UnexpectMsg);
         };
-        // Any of the size parameters in the InitAck must be less or equal than the one in the InitSyn,
-        // otherwise the InitAck message is considered invalid and it should be treated as a
-        // CLOSE message with L==0 by the Initiating Peer -- the recipient of the InitAck message.
-        params.seq_num_res = if params.seq_num_res >= iam.seq_num_res {
-            iam.seq_num_res
-        } else {
-            return Err(TransportError::OpenSnResolution);
-        };
 
-        params.req_id_res = if params.req_id_res >= iam.req_id_res {
-            iam.req_id_res
-        } else {
-            return Err(TransportError::OpenSnResolution);
-        };
+        if iam.version() != Z_PROTO_VERSION {
+            return Err(ProtocolError::VersionMismatch(iam.version()).into());
+        }
+        // Without the S flag the InitAck accepts the size parameters of the
+        // InitSyn as they are.
+        if iam.has_size_params() {
+            // Any of the size parameters in the InitAck must be less or equal than the one in the InitSyn,
+            // otherwise the InitAck message is considered invalid and it should be treated as a
+            // CLOSE message with L==0 by the Initiating Peer -- the recipient of the InitAck message.
+            params.seq_num_res = if params.seq_num_res >= iam.seq_num_res {
+                iam.seq_num_res
+            } else {
+                return Err(TransportError::OpenSnResolution);
+            };
 
-        params.batch_size = if params.batch_size >= iam.batch_size {
-            iam.batch_size
-        } else {
-            return Err(TransportError::OpenSnResolution);
-        };
+            params.req_id_res = if params.req_id_res >= iam.req_id_res {
+                iam.req_id_res
+            } else {
+                return Err(TransportError::OpenSnResolution);
+            };
 
-        params.key_id_res = 0x08 << params.key_id_res;
-        params.req_id_res = 0x08 << params.req_id_res;
+            // A larger batch than ours is clamped rather than refused, an
+            // empty one can never be honored.
+            if iam.batch_size == 0 {
+                return Err(TransportError::InvalidBatchSize);
+            }
+            params.batch_size = iam.batch_size.min(params.batch_size);
+        }
+        let link_batch = self.intf.mtu.min(u16::MAX as usize) as u16;
+        params.batch_size = params.batch_size.min(link_batch);
 
-        params.initial_sn_tx = SmallRng::seed_from_u64(0).random();
-        params.initial_sn_tx = params.initial_sn_tx & !_z_sn_modulo_mask(params.seq_num_res);
+        params.initial_sn_tx = initial_sn(cfg.sn_seed, _z_sn_modulo_mask(params.seq_num_res));
 
+        // QoS is only used when both sides asked for it.
+        params.is_qos = cfg.qos && iam.qos;
+        params.patch = if (0..=Z_PATCH).contains(&iam.patch) {
+            iam.patch
+        } else {
+            0
+        };
         params.zid = iam.zid;
+        params.whatami = iam.whatami();
 
-        OpenSyn::new(
-            Z_TRANSPORT_LEASE,
-            params.initial_sn_tx,
-            Some(&iam.cookie.unwrap()),
-        )
-        .encode(&mut self.open_cache)?;
+        // The cookie is copied out so the OpenSyn can reuse the buffer the
+        // InitAck was received in.
+        let cookie = iam.cookie.ok_or(TransportError::MissingCookie)?;
+        let cookie: Vec<u8, Z_MAX_COOKIE_SIZE> =
+            Vec::from_slice(cookie).map_err(|_| TransportError::CookieTooLarge(cookie.len()))?;
+        drop(s);
+
+        self.cache.clear();
+        OpenSyn::new(cfg.lease_ms, params.initial_sn_tx, Some(&cookie)).encode(&mut self.cache)?;
         #[cfg(feature = "defmt")]
         defmt::debug!("Sending Z_OPEN(Syn)");
-        self.intf.send_msg(&self.open_cache.as_slice())?;
-        self.open_cache.clear();
+        self.intf.send_msg(self.cache.as_slice())?;
+        params.stats.tx_bytes += self.cache.len();
+        params.stats.check(cfg.max_handshake_bytes)?;
+        self.cache.clear();
 
-        let mut s = self.open_cache.extract_slice(self.intf.mtu)?;
+        let mut s = self.cache.extract_slice(self.intf.mtu)?;
         let size = self.intf.recv_msg(s.as_mut())?;
-        s.truncate(size);
-        let oam = TransportMessage::decode(&mut s)?;
+        s.truncate(size)?;
+        params.stats.rx_bytes += size;
+        params.stats.check(cfg.max_handshake_bytes)?;
+        let oam = TransportMessage::decode(&s)?;
 
-        let oam = if let TransportMessage {
-            body: TransportBody::OpenAck(oam),
-        } = oam
-        {
-            #[cfg(feature = "defmt")]
-            defmt::debug!("Received Z_OPEN(Ack)");
-            oam
-        } else {
-            return Err(TransportError::UnexpectMsg);
+        let oam = match oam.body {
+            TransportBody::OpenAck(oam) => {
+                #[cfg(feature = "defmt")]
+                defmt::debug!("Received Z_OPEN(Ack)");
+                oam
+            }
+            // The peer answers an OpenSyn whose cookie it cannot validate with
+            // a Close instead of an OpenAck.
+            TransportBody::Close(Close {
+                reason: reason::INVALID,
+                ..
+            }) => return Err(TransportError::CookieMismatch),
+            _ => return Err(TransportError::UnexpectMsg),
         };
 
         #[cfg(feature = "defmt")]
@@ -159,21 +258,802 @@ impl<L: LinkIntf> Unicast<L> {
 
         params.lease = oam.lease;
         params.initial_sn_rx = oam.initial_sn;
+        self.state = UnicastState::Opened;
+
+        if let Some(on_established) = cfg.on_established {
+            on_established(&params);
+        }
 
         Ok(params)
     }
 
-    pub fn update(&mut self, _params: &UnicastParams) -> Result<(), TransportError> {
+    /// Applies the negotiated `params`: the link MTU is clamped to the agreed
+    /// batch size and the SN resolution is kept for the frames to come.
+    pub fn update(&mut self, params: UnicastParams) -> Result<(), TransportError> {
+        self.sn_tx = params.initial_sn_tx;
+        self.sn_rx = params.initial_sn_rx;
+        self.sn_mask = _z_sn_modulo_mask(params.seq_num_res);
+        self.inflight = 0;
+        self.intf.mtu = self.intf.mtu.min(params.batch_size as usize);
+        self.params = params;
+        Ok(())
+    }
+
+    /// Re-opens the link and runs a fresh handshake, anything still batched
+    /// is dropped.
+    pub fn reconnect(&mut self, cfg: &Config) -> Result<(), TransportError> {
+        self.batch.clear();
+        self.intf.open()?;
+        let params = self.handshake(cfg)?;
+        self.update(params)
+    }
+
+    pub fn state(&self) -> UnicastState {
+        self.state
+    }
+
+    pub fn params(&self) -> &UnicastParams {
+        &self.params
+    }
+
+    pub fn link_capabilities(&self) -> LinkCapabilities {
+        self.intf.cap
+    }
+
+    pub fn peer(&self) -> (ZenohID, WhatAmI) {
+        (self.params.zid, self.params.whatami)
+    }
+
+    /// Most reliable frames in flight: half the SN space, past which the
+    /// peer could no longer tell a new SN from an old one.
+    pub fn max_inflight(&self) -> u64 {
+        (self.sn_mask >> 1) + 1
+    }
+
+    pub fn inflight(&self) -> u64 {
+        self.inflight
+    }
+
+    /// Bounds the reliable frames in flight by [`Unicast::max_inflight`]. The
+    /// peer sends no acknowledgements, so the caller then has to release
+    /// frames itself with [`Unicast::acknowledge`].
+    pub fn set_ack_window(&mut self, enabled: bool) {
+        self.ack_window = enabled;
+        self.inflight = 0;
+    }
+
+    /// Releases `frames` reliable frames from the window, once the peer is
+    /// known to have received them.
+    pub fn acknowledge(&mut self, frames: u64) {
+        self.inflight = self.inflight.saturating_sub(frames);
+    }
+
+    pub fn send(&mut self, msg: &NetworkMessage) -> Result<(), TransportError> {
+        self.send_on(msg, Priority::default())
+    }
+
+    /// Sends `msg` with `priority`, which is only tagged on the frame when QoS
+    /// has been negotiated with the peer.
+    pub fn send_on(
+        &mut self,
+        msg: &NetworkMessage,
+        priority: Priority,
+    ) -> Result<(), TransportError> {
+        self.queue_on(msg, priority, Reliability::default())?;
+
+        if !self.batch.is_batching() {
+            self.send_batch()?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends `msg` to the pending batch, which only goes out once full or
+    /// on [`Unicast::flush`]. Without QoS only the default priority is allowed.
+    pub fn queue_on(
+        &mut self,
+        msg: &NetworkMessage,
+        priority: Priority,
+        reliability: Reliability,
+    ) -> Result<(), TransportError> {
+        self.queue_item(msg, priority, reliability)
+    }
+
+    /// Like [`Unicast::queue_on`] for a network message encoded beforehand.
+    pub fn queue_encoded(
+        &mut self,
+        msg: &[u8],
+        priority: Priority,
+        reliability: Reliability,
+    ) -> Result<(), TransportError> {
+        self.queue_item(msg, priority, reliability)
+    }
+
+    fn queue_item<M: BatchItem + ?Sized>(
+        &mut self,
+        msg: &M,
+        priority: Priority,
+        reliability: Reliability,
+    ) -> Result<(), TransportError> {
+        if self.state != UnicastState::Opened {
+            return Err(TransportError::NotOpen);
+        }
+        if !self.params.is_qos && priority != Priority::default() {
+            return Err(TransportError::QosNotNegotiated);
+        }
+
+        let qos = self.params.is_qos.then_some(priority as u8);
+        if !self.push(qos, reliability, msg)? {
+            self.send_batch()?;
+            if !self.push(qos, reliability, msg)? {
+                return Err(TransportError::EncodeError(WriteError::DidntWrite));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pushes `msg` into the batch, a new frame takes the next SN which is
+    /// given back if the message does not fit. A new reliable frame needs
+    /// room in the window.
+    fn push<M: BatchItem + ?Sized>(
+        &mut self,
+        qos: Option<u8>,
+        reliability: Reliability,
+        msg: &M,
+    ) -> Result<bool, TransportError> {
+        if !self.batch.is_empty() {
+            return self
+                .batch
+                .push(self.sn_tx, qos, reliability, msg, self.intf.mtu);
+        }
+
+        let reliable = self.ack_window && reliability.is_reliable();
+        if reliable && self.inflight >= self.max_inflight() {
+            return Err(TransportError::WindowFull);
+        }
+
+        let sn = self.next_sn();
+        let pushed = self.batch.push(sn, qos, reliability, msg, self.intf.mtu);
+        match pushed {
+            Ok(true) if reliable => self.inflight += 1,
+            Ok(true) => {}
+            _ => self.sn_tx = sn,
+        }
+        pushed
+    }
+
+    /// Returns the SN for the next frame and advances the counter, wrapping
+    /// at the negotiated resolution.
+    pub fn next_sn(&mut self) -> u64 {
+        let sn = self.sn_tx;
+        self.sn_tx = sn.wrapping_add(1) & self.sn_mask;
+        sn
+    }
+
+    /// Sends the pending batch, if any.
+    pub fn flush(&mut self) -> Result<(), TransportError> {
+        self.send_batch()
+    }
+
+    /// Reads one batch from the link and hands every network message carried
+    /// by its Frame to `on_msg`.
+    pub fn recv<F: FnMut(&NetworkMessage)>(&mut self, mut on_msg: F) -> Result<(), TransportError> {
+        if self.state != UnicastState::Opened {
+            return Err(TransportError::NotOpen);
+        }
+
+        self.cache.clear();
+        let mut s = self.cache.extract_slice(self.intf.mtu)?;
+        let size = self.intf.recv_msg(s.as_mut())?;
+        s.truncate(size)?;
+
+        match TransportMessage::decode(&s)?.body {
+            TransportBody::Frame(frame) => {
+                let expected = self.sn_rx;
+                self.sn_rx = frame.sn.wrapping_add(1) & self.sn_mask;
+                if frame.reliable && frame.sn != expected {
+                    return Err(TransportError::SequenceGap {
+                        expected,
+                        got: frame.sn,
+                    });
+                }
+
+                for msg in Frame::messages(&s) {
+                    on_msg(&msg?);
+                }
+            }
+            TransportBody::Close(close) => {
+                #[cfg(feature = "defmt")]
+                defmt::debug!("Received Z_CLOSE, reason {}", close.reason);
+                self.last_close = Some(close.reason.into());
+                self.state = UnicastState::Closed;
+            }
+            _ => return Err(TransportError::UnexpectMsg),
+        }
+
+        Ok(())
+    }
+
+    /// Reads one batch from the link and drops it without decoding the
+    /// network messages of its Frame, e.g. to step over one carrying a
+    /// message we do not support. The RX SN still advances past the Frame.
+    pub fn discard_frame(&mut self) -> Result<(), TransportError> {
+        if self.state != UnicastState::Opened {
+            return Err(TransportError::NotOpen);
+        }
+
+        self.cache.clear();
+        let mut s = self.cache.extract_slice(self.intf.mtu)?;
+        let size = self.intf.recv_msg(s.as_mut())?;
+        s.truncate(size)?;
+
+        match TransportMessage::decode(&s)?.body {
+            TransportBody::Frame(frame) => {
+                self.sn_rx = frame.sn.wrapping_add(1) & self.sn_mask;
+            }
+            TransportBody::Close(close) => {
+                self.last_close = Some(close.reason.into());
+                self.state = UnicastState::Closed;
+            }
+            _ => return Err(TransportError::UnexpectMsg),
+        }
+
+        Ok(())
+    }
+
+    /// Reason of the last Close received from the peer.
+    pub fn last_close_reason(&self) -> Option<CloseReason> {
+        self.last_close
+    }
+
+    pub fn begin_batch(&mut self) {
+        self.batch.begin();
+    }
+
+    pub fn flush_batch(&mut self) -> Result<(), TransportError> {
+        self.batch.end();
+        self.send_batch()
+    }
+
+    fn send_batch(&mut self) -> Result<(), TransportError> {
+        if !self.batch.is_empty() {
+            let sent = self.intf.send_msg(self.batch.as_slice());
+            // Part of the frame left, it cannot be sent again as is
+            if let Err(TransportError::LinkError(LinkError::PartialWrite { .. })) = sent {
+                self.batch.clear();
+            }
+            sent?;
+        }
+        self.batch.clear();
+
+        Ok(())
+    }
+
+    pub fn set_batch_window(&mut self, window_ms: u32) {
+        self.batch.set_window(window_ms);
+    }
+
+    /// Flushes the pending batch once its window has elapsed.
+    pub fn tick(&mut self, elapsed_ms: u32) -> Result<(), TransportError> {
+        if self.batch.tick(elapsed_ms) {
+            self.send_batch()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn close(&mut self, reason: CloseReason) -> Result<(), TransportError> {
+        #[cfg(feature = "defmt")]
+        defmt::debug!("Sending Z_CLOSE");
+
+        self.cache.clear();
+        Close::message(true, reason.into()).encode(&mut self.cache)?;
+        self.intf.send_msg(self.cache.as_slice())?;
+        self.cache.clear();
+        self.state = UnicastState::Closed;
+
         Ok(())
     }
+
+    pub fn drain(&mut self, timeout_ms: u32) -> Result<(), TransportError> {
+        self.intf.drain(timeout_ms)
+    }
+}
+
+/// Picks the initial TX sequence number within `mask`.
+#[cfg(feature = "rand")]
+fn initial_sn(seed: u64, mask: u64) -> u64 {
+    SmallRng::seed_from_u64(seed).random::<u64>() & mask
+}
+
+/// Without `rand` the seed itself is the initial sequence number.
+#[cfg(not(feature = "rand"))]
+fn initial_sn(seed: u64, mask: u64) -> u64 {
+    seed & mask
+}
+
+/// Width in bits of the resolution with wire code `res`.
+pub fn resolution_bits(res: u8) -> u32 {
+    8 << (res & 0x03)
 }
 
-fn _z_sn_modulo_mask(bits: u8) -> u32 {
+pub(super) fn _z_sn_modulo_mask(bits: u8) -> u64 {
     match bits {
-        0x00 => (u8::MAX >> 1) as u32,
-        0x01 => (u16::MAX >> 2) as u32,
-        0x02 => u32::MAX >> 4,
-        0x03 => (u64::MAX >> 1) as u32,
+        0x00 => (u8::MAX >> 1) as u64,
+        0x01 => (u16::MAX >> 2) as u64,
+        0x02 => (u32::MAX >> 4) as u64,
+        0x03 => u64::MAX >> 1,
         _ => unreachable!(),
     }
 }
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::iobuf::{SliceReader, Writer};
+    use crate::link::mock::{MockIo, MockLink};
+    use crate::protocol::{keyexpr::KeyExpr, network::push::Push};
+    use crate::transport::Transport;
+    use crate::ConfigBuilder;
+
+    fn client() -> ConfigBuilder {
+        Config::builder(ZenohID::from(0x49), WhatAmI::Client)
+    }
+
+    fn handshake_with(
+        cfg: &Config,
+        ack: TransportMessage,
+    ) -> Result<UnicastParams, TransportError> {
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut().push_message(&ack)?;
+        io.borrow_mut().push_open_ack(0)?;
+
+        Transport::handshake_only(MockLink::unicast(&io), cfg)
+    }
+
+    fn init_ack(batch_size: u16) -> TransportMessage<'static> {
+        let mut ack = InitSyn::ack(WhatAmI::Router, ZenohID::from(0xbeef), &[0xc0]);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.batch_size = batch_size;
+        }
+        ack
+    }
+
+    #[test]
+    fn larger_peer_batch_size_is_clamped() {
+        let params = handshake_with(&client().build(), init_ack(u16::MAX)).unwrap();
+        assert_eq!(params.batch_size, crate::Z_BATCH_UNICAST_SIZE);
+    }
+
+    #[test]
+    fn on_established_sees_the_negotiated_params() {
+        static ESTABLISHED: std::sync::Mutex<Option<UnicastParams>> = std::sync::Mutex::new(None);
+
+        let cfg = client()
+            .on_established(|params| *ESTABLISHED.lock().unwrap() = Some(params.clone()))
+            .build();
+        let params = handshake_with(&cfg, init_ack(1024)).unwrap();
+
+        assert_eq!(params.batch_size, 1024);
+        assert_eq!(ESTABLISHED.lock().unwrap().as_ref(), Some(&params));
+    }
+
+    #[test]
+    fn params_can_be_cloned_and_compared() {
+        let params = handshake_with(&client().build(), init_ack(1024)).unwrap();
+        let copy = params.clone();
+        assert_eq!(copy, params);
+
+        let other = handshake_with(&client().build(), init_ack(512)).unwrap();
+        assert_ne!(other, params);
+    }
+
+    #[test]
+    fn zero_batch_size_is_rejected() {
+        assert!(matches!(
+            handshake_with(&client().build(), init_ack(0)),
+            Err(TransportError::InvalidBatchSize)
+        ));
+    }
+
+    #[test]
+    fn init_ack_without_sizes_keeps_the_proposal() {
+        // Left out of the wire, the InitAck then carries no S flag
+        let mut ack = init_ack(8192);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.seq_num_res = 2;
+            iam.req_id_res = 2;
+        }
+
+        let params = handshake_with(&client().build(), ack).unwrap();
+        assert_eq!(params.seq_num_res, crate::Z_SN_RESOLUTION);
+        assert_eq!(params.req_id_res, crate::Z_REQ_RESOLUTION);
+        assert_eq!(params.batch_size, crate::Z_BATCH_UNICAST_SIZE);
+    }
+
+    #[test]
+    fn configured_lease_goes_into_the_open_syn() {
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let cfg = client().lease_ms(2500).build();
+        Transport::handshake_only(MockLink::unicast(&io), &cfg).unwrap();
+
+        let io = io.borrow();
+        let reader = SliceReader::new(&io.outbound()[1]);
+        let TransportBody::OpenSyn(osm) = TransportMessage::decode(&reader).unwrap().body else {
+            panic!("expected an OpenSyn");
+        };
+        assert_eq!(osm.lease, 2500);
+    }
+
+    #[test]
+    fn open_syn_echoes_the_init_ack_cookie() {
+        let cookie: [u8; 40] = core::array::from_fn(|i| (i as u8).wrapping_mul(37));
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_init_ack(ZenohID::from(0xbeef), &cookie)
+            .unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        Transport::handshake_only(MockLink::unicast(&io), &client().build()).unwrap();
+
+        let io = io.borrow();
+        let reader = SliceReader::new(&io.outbound()[1]);
+        let TransportBody::OpenSyn(osm) = TransportMessage::decode(&reader).unwrap().body else {
+            panic!("expected an OpenSyn");
+        };
+        assert_eq!(osm.cookie, Some(&cookie[..]));
+    }
+
+    #[test]
+    fn oversized_init_ack_exceeds_the_handshake_budget() {
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_init_ack(ZenohID::from(0xbeef), &[0xc0; 200])
+            .unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        let cfg = client().max_handshake_bytes(64).build();
+
+        assert!(matches!(
+            Transport::handshake_only(MockLink::unicast(&io), &cfg),
+            Err(TransportError::HandshakeBudgetExceeded)
+        ));
+        // Aborted before the OpenSyn went out
+        assert_eq!(io.borrow().outbound().len(), 1);
+        assert_eq!(io.borrow().pending_inbound(), 1);
+    }
+
+    #[test]
+    fn cookie_larger_than_the_bound_is_an_error() {
+        let cookie = [0xc0; crate::Z_MAX_COOKIE_SIZE + 1];
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_init_ack(ZenohID::from(0xbeef), &cookie)
+            .unwrap();
+
+        assert!(matches!(
+            Transport::handshake_only(MockLink::unicast(&io), &client().build()),
+            Err(TransportError::CookieTooLarge(len)) if len == cookie.len()
+        ));
+    }
+
+    #[test]
+    fn init_ack_without_cookie_bytes_opens_with_an_empty_cookie() {
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_inbound(&[
+                0x61, // |Z=0|S=1|A=1| INIT
+                0x09, // version
+                0x10, // zid_len=1 (2 bytes) | whatami Router
+                0xef, 0xbe, // zid
+                0x0a, // kid=0 | rid=32 bits | fsn=32 bits
+                0x00, 0x08, // batch size, no cookie follows
+            ])
+            .unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        Transport::handshake_only(MockLink::unicast(&io), &client().build()).unwrap();
+
+        let io = io.borrow();
+        let reader = SliceReader::new(&io.outbound()[1]);
+        let TransportBody::OpenSyn(osm) = TransportMessage::decode(&reader).unwrap().body else {
+            panic!("expected an OpenSyn");
+        };
+        assert_eq!(osm.cookie, Some(&[][..]));
+    }
+
+    #[test]
+    fn rejected_cookie_is_a_cookie_mismatch() {
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_init_ack(ZenohID::from(0xbeef), &[0xc0])
+            .unwrap();
+        io.borrow_mut()
+            .push_message(&Close::message(true, reason::INVALID))
+            .unwrap();
+        assert!(matches!(
+            Transport::handshake_only(MockLink::unicast(&io), &client().build()),
+            Err(TransportError::CookieMismatch)
+        ));
+
+        // An empty cookie is echoed as is and accepted
+        let params = handshake_with(
+            &client().build(),
+            InitSyn::ack(WhatAmI::Router, ZenohID::from(0xbeef), &[]),
+        )
+        .unwrap();
+        assert_eq!(params.zid, ZenohID::from(0xbeef));
+    }
+
+    #[test]
+    fn qos_needs_both_sides() {
+        let mut ack = init_ack(crate::Z_BATCH_UNICAST_SIZE);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.qos = true;
+        }
+        assert!(
+            handshake_with(&client().qos(true).build(), ack)
+                .unwrap()
+                .is_qos
+        );
+
+        let mut ack = init_ack(crate::Z_BATCH_UNICAST_SIZE);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.qos = true;
+        }
+        assert!(!handshake_with(&client().build(), ack).unwrap().is_qos);
+    }
+
+    #[test]
+    fn user_data_needs_an_opened_transport() {
+        let io = RefCell::new(MockIo::new());
+        let mut unicast = Unicast::new(crate::link::open(MockLink::unicast(&io)).unwrap());
+        assert_eq!(unicast.state(), UnicastState::Closed);
+
+        let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/a").unwrap(), b"x"));
+        assert!(matches!(unicast.send(&msg), Err(TransportError::NotOpen)));
+        assert!(matches!(unicast.recv(|_| {}), Err(TransportError::NotOpen)));
+        assert!(io.borrow().outbound().is_empty());
+
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let params = unicast.handshake(&client().build()).unwrap();
+        unicast.update(params).unwrap();
+        assert_eq!(unicast.state(), UnicastState::Opened);
+        unicast.send(&msg).unwrap();
+
+        unicast.close(CloseReason::Generic).unwrap();
+        assert!(matches!(unicast.send(&msg), Err(TransportError::NotOpen)));
+    }
+
+    #[test]
+    fn discarded_frame_does_not_break_the_sequence() {
+        let io = RefCell::new(MockIo::new());
+        let mut unicast = Unicast::new(crate::link::open(MockLink::unicast(&io)).unwrap());
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let params = unicast.handshake(&client().build()).unwrap();
+        unicast.update(params).unwrap();
+
+        // SN 0 carries an OAM, which is not supported
+        let mut buf = ZVec::new();
+        Frame::message(true, 0).encode(&mut buf).unwrap();
+        buf.write_exact(&[0x1f, 0x00]).unwrap();
+        io.borrow_mut().push_inbound(buf.as_slice()).unwrap();
+        let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/a").unwrap(), b"x"));
+        io.borrow_mut().push_frame(1, &[msg]).unwrap();
+
+        unicast.discard_frame().unwrap();
+        let mut pushes = 0;
+        unicast
+            .recv(|msg| {
+                assert!(matches!(msg, NetworkMessage::Push(_)));
+                pushes += 1;
+            })
+            .unwrap();
+        assert_eq!(pushes, 1);
+    }
+
+    #[test]
+    fn missing_reliable_frame_is_a_sequence_gap() {
+        let io = RefCell::new(MockIo::new());
+        let mut unicast = Unicast::new(crate::link::open(MockLink::unicast(&io)).unwrap());
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let params = unicast.handshake(&client().build()).unwrap();
+        unicast.update(params).unwrap();
+
+        for sn in [0, 1, 3] {
+            let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/a").unwrap(), b"x"));
+            io.borrow_mut().push_frame(sn, &[msg]).unwrap();
+        }
+
+        unicast.recv(|_| {}).unwrap();
+        unicast.recv(|_| {}).unwrap();
+        assert!(matches!(
+            unicast.recv(|_| {}),
+            Err(TransportError::SequenceGap {
+                expected: 2,
+                got: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn reliable_frames_are_bounded_by_the_sn_window() {
+        let io = RefCell::new(MockIo::new());
+        let mut ack = init_ack(crate::Z_BATCH_UNICAST_SIZE);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.seq_num_res = 0;
+        }
+        io.borrow_mut().push_message(&ack).unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        let mut unicast = Unicast::new(crate::link::open(MockLink::unicast(&io)).unwrap());
+        let params = unicast.handshake(&client().build()).unwrap();
+        unicast.update(params).unwrap();
+        unicast.set_ack_window(true);
+
+        // 8-bit SNs wrap at 128, half of them may be in flight
+        assert_eq!(unicast.max_inflight(), 64);
+        let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/a").unwrap(), b"x"));
+        for _ in 0..64 {
+            unicast
+                .queue_on(&msg, Priority::default(), Reliability::Reliable)
+                .unwrap();
+            unicast.flush().unwrap();
+            io.borrow_mut().clear_outbound();
+        }
+        assert!(matches!(
+            unicast.queue_on(&msg, Priority::default(), Reliability::Reliable),
+            Err(TransportError::WindowFull)
+        ));
+
+        // Best effort frames are not tracked
+        unicast
+            .queue_on(&msg, Priority::default(), Reliability::BestEffort)
+            .unwrap();
+        unicast.flush().unwrap();
+
+        unicast.acknowledge(1);
+        unicast
+            .queue_on(&msg, Priority::default(), Reliability::Reliable)
+            .unwrap();
+    }
+
+    #[test]
+    fn next_sn_wraps_at_the_negotiated_resolution() {
+        let io = RefCell::new(MockIo::new());
+        let mut ack = init_ack(crate::Z_BATCH_UNICAST_SIZE);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.seq_num_res = 0;
+        }
+        io.borrow_mut().push_message(&ack).unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        let mut unicast = Unicast::new(crate::link::open(MockLink::unicast(&io)).unwrap());
+        let mut params = unicast.handshake(&client().build()).unwrap();
+        params.initial_sn_tx = 0x7e;
+        unicast.update(params).unwrap();
+        io.borrow_mut().clear_outbound();
+
+        // 8-bit SNs wrap at 127
+        let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/a").unwrap(), b"x"));
+        for _ in 0..3 {
+            unicast.send(&msg).unwrap();
+        }
+        let io = io.borrow();
+        let sns: heapless::Vec<u64, 3> = io
+            .outbound()
+            .iter()
+            .map(|frame| {
+                let reader = SliceReader::new(frame);
+                match TransportMessage::decode(&reader).unwrap().body {
+                    TransportBody::Frame(frame) => frame.sn,
+                    _ => panic!("expected a Frame"),
+                }
+            })
+            .collect();
+        assert_eq!(sns, [0x7e, 0x7f, 0]);
+    }
+
+    #[test]
+    fn sns_above_u32_are_kept_at_64_bit_resolution() {
+        let io = RefCell::new(MockIo::new());
+        let mut unicast = Unicast::new(crate::link::open(MockLink::unicast(&io)).unwrap());
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let mut params = unicast.handshake(&client().build()).unwrap();
+        let sn = u32::MAX as u64 + 1;
+        params.seq_num_res = 0x03;
+        params.initial_sn_rx = sn;
+        unicast.update(params).unwrap();
+
+        for sn in [sn, sn + 1] {
+            let msg = NetworkMessage::Push(Push::new(KeyExpr::try_from("demo/a").unwrap(), b"x"));
+            io.borrow_mut().push_frame(sn, &[msg]).unwrap();
+        }
+        unicast.recv(|_| {}).unwrap();
+        unicast.recv(|_| {}).unwrap();
+    }
+
+    #[test]
+    fn unsupported_patch_falls_back_to_zero() {
+        let mut ack = init_ack(crate::Z_BATCH_UNICAST_SIZE);
+        if let TransportBody::InitAck(iam) = &mut ack.body {
+            iam.patch = Z_PATCH + 1;
+        }
+        assert_eq!(handshake_with(&client().build(), ack).unwrap().patch, 0);
+    }
+
+    #[test]
+    fn resolution_codes_map_to_their_width() {
+        let widths: [u32; 4] = core::array::from_fn(|res| resolution_bits(res as u8));
+        assert_eq!(widths, [8, 16, 32, 64]);
+    }
+
+    #[test]
+    fn update_clamps_the_mtu_to_the_batch_size() {
+        let io = RefCell::new(MockIo::new());
+        let mut unicast = Unicast::new(crate::link::open(MockLink::unicast(&io)).unwrap());
+        assert_eq!(unicast.intf.mtu, crate::Z_MAX_MTU);
+
+        unicast
+            .update(UnicastParams {
+                batch_size: 256,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(unicast.intf.mtu, 256);
+    }
+
+    #[test]
+    fn one_buffer_serves_the_whole_handshake() {
+        // The cookie is copied out before the OpenSyn is encoded over it
+        let cookie: [u8; 64] = core::array::from_fn(|i| i as u8);
+        let io = RefCell::new(MockIo::new());
+        io.borrow_mut()
+            .push_init_ack(ZenohID::from(0xbeef), &cookie)
+            .unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        let mut unicast = Unicast::new(crate::link::open(MockLink::unicast(&io)).unwrap());
+
+        unicast.handshake(&client().build()).unwrap();
+        assert!(unicast.cache.is_empty());
+
+        let io = io.borrow();
+        let reader = SliceReader::new(&io.outbound()[1]);
+        let TransportBody::OpenSyn(osm) = TransportMessage::decode(&reader).unwrap().body else {
+            panic!("expected an OpenSyn");
+        };
+        assert_eq!(osm.cookie, Some(&cookie[..]));
+    }
+
+    #[test]
+    fn consecutive_handshakes_succeed() {
+        let io = RefCell::new(MockIo::new());
+        let cfg = client().build();
+        let mut unicast = Unicast::new(crate::link::open(MockLink::unicast(&io)).unwrap());
+
+        io.borrow_mut()
+            .push_handshake(ZenohID::from(0xbeef))
+            .unwrap();
+        let params = unicast.handshake(&cfg).unwrap();
+        unicast.update(params).unwrap();
+        assert_eq!(unicast.peer().0, ZenohID::from(0xbeef));
+
+        io.borrow_mut()
+            .push_init_ack(ZenohID::from(0xcafe), &[0x01, 0x02])
+            .unwrap();
+        io.borrow_mut().push_open_ack(0).unwrap();
+        unicast.reconnect(&cfg).unwrap();
+        assert_eq!(unicast.state(), UnicastState::Opened);
+        assert_eq!(unicast.peer().0, ZenohID::from(0xcafe));
+        assert_eq!(io.borrow().outbound().len(), 4);
+    }
+}
@@ -0,0 +1,47 @@
+//! Opens sessions against the fake router of `link::mock` over a TCP loopback.
+#![cfg(feature = "std")]
+
+use zenoh_client_rs::{
+    link::{mock::FakeRouter, tcp::TcpIntf},
+    protocol::{whatami::WhatAmI, ZenohID},
+    transport::Transport,
+    Config,
+};
+
+const CLIENT: u128 = 0x49;
+const ROUTER: u128 = 0xbeef;
+
+#[test]
+fn session_opens_against_the_fake_router() {
+    let router = FakeRouter::bind(ZenohID::from(ROUTER)).unwrap();
+    let addr = router.local_addr().unwrap();
+    let served = router.spawn();
+
+    let cfg = Config::new(ZenohID::from(CLIENT), WhatAmI::Client);
+    let session = zenoh_client_rs::open(TcpIntf::connect(addr).unwrap(), &cfg).unwrap();
+    let info = session.info();
+    assert_eq!(info.peer_zid, Some(ZenohID::from(ROUTER)));
+    assert_eq!(info.peer_whatami, Some(WhatAmI::Router));
+
+    drop(session);
+    assert_eq!(served.join().unwrap().unwrap(), ZenohID::from(CLIENT));
+}
+
+#[test]
+fn handshake_negotiates_with_the_fake_router() {
+    let router = FakeRouter::bind(ZenohID::from(ROUTER))
+        .unwrap()
+        .with_batch_size(1024);
+    let addr = router.local_addr().unwrap();
+    let served = router.spawn();
+
+    let cfg = Config::new(ZenohID::from(CLIENT), WhatAmI::Client);
+    let params = Transport::handshake_only(TcpIntf::connect(addr).unwrap(), &cfg).unwrap();
+    assert_eq!(params.zid, ZenohID::from(ROUTER));
+    assert_eq!(params.whatami, WhatAmI::Router);
+    assert_eq!(params.batch_size, 1024);
+    assert_eq!(params.initial_sn_rx, 0);
+    assert!(params.stats.tx_bytes > 0 && params.stats.rx_bytes > 0);
+
+    served.join().unwrap().unwrap();
+}